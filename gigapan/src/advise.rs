@@ -0,0 +1,361 @@
+//! Rank every move from a position in the middle of a queue by the limited see
+//! chance after it, for when the best move matters more than the overall chance.
+//!
+//! A position is the board, the piece in hold if any, and the pieces seen so
+//! far.  The pattern covers the whole queue from the current piece on, with
+//! the hold in front like `(T)`, and the seen pieces must be able to start it.
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::Write;
+
+use hashbrown::HashSet;
+use legal_boards::boardgraph::{FrozenGigapan, GigapanLookup};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use srs_4l::feasibility::Feasibility;
+use srs_4l::gameplay::{Board, Shape};
+
+use crate::calculate::{fills_board, max_limited_see_queues, LimitedSeeMemo, OutputFormat, Search};
+use crate::fumens::{encode_boards, encode_path};
+use crate::queue::{count_queues, Bag, CombinatoricQueue, QueueState};
+
+/// One move from the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Move {
+    /// Place the current piece
+    Place(Shape, Board),
+    /// Put the current piece in the empty hold
+    Hold(Shape),
+    /// Swap the current piece into hold and place the held piece
+    Swap(Shape, Board),
+}
+
+impl Move {
+    /// The board after the move
+    pub fn board(self, start: Board) -> Board {
+        match self {
+            Move::Place(_, board) | Move::Swap(_, board) => board,
+            Move::Hold(_) => start,
+        }
+    }
+
+    /// The piece placed, if any
+    pub fn placed(self) -> Option<Shape> {
+        match self {
+            Move::Place(shape, _) | Move::Swap(shape, _) => Some(shape),
+            Move::Hold(_) => None,
+        }
+    }
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Move::Place(shape, _) => write!(f, "place {}", shape.name()),
+            Move::Hold(shape) => write!(f, "hold {}", shape.name()),
+            Move::Swap(shape, _) => write!(f, "hold, place {}", shape.name()),
+        }
+    }
+}
+
+/// A move with the hidden queues after it which still pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advice {
+    pub action: Move,
+    pub passing: usize,
+    pub total: usize,
+}
+
+impl Advice {
+    pub fn chance(&self) -> f64 {
+        self.passing as f64 / self.total as f64 * 100.0
+    }
+}
+
+/// Every move from a position, best first
+pub struct Advised {
+    pub board: Board,
+    pub hold: Option<Shape>,
+    pub seen: Vec<Shape>,
+    pub pattern: String,
+    pub use_hold: bool,
+    pub culled: bool,
+    pub two_line: bool,
+    pub moves: Vec<Advice>,
+}
+
+/// A point in the queue where the next move is chosen, or where a move has
+/// just been made and the next piece is still to be revealed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub board: Board,
+    pub hold: Option<Shape>,
+    /// Whether the last move was holding, so the current piece can't be held
+    pub just_held: bool,
+    /// The current piece, then the previews
+    pub queue: VecDeque<Shape>,
+    /// What's left of the pattern after the revealed pieces
+    queue_state: QueueState,
+    /// How many pieces of the pattern are revealed, the hold included
+    revealed: usize,
+}
+
+impl Position {
+    /// The position with the pattern's hold in hold and the first pieces of
+    /// the pattern seen
+    pub fn new(board: Board, combinatoric_queue: &CombinatoricQueue, seen: &[Shape]) -> Result<Self, String> {
+        let counted_bags = combinatoric_queue.get_counted_bags();
+        Position::start(board, &counted_bags, combinatoric_queue.hold(), seen)
+            .ok_or_else(|| format!("{} can't start {combinatoric_queue}", seen.iter().map(|shape| shape.name()).collect::<String>()))
+    }
+
+    /// The position with `hold` and then `seen` taken from the start of the
+    /// counted bags, if they can start them
+    pub(crate) fn start(board: Board, counted_bags: &[(u8, Bag)], hold: Option<Shape>, seen: &[Shape]) -> Option<Self> {
+        let revealed = hold.is_some() as usize + seen.len();
+        if revealed > counted_bags.len() {
+            return None;
+        }
+        let mut queue_state = QueueState(counted_bags[0].1.full);
+        for ((bag_placement, bag), &shape) in counted_bags.iter().zip(hold.iter().chain(seen)) {
+            let state = if bag_placement == &0 { queue_state.next(bag) } else { queue_state };
+            queue_state = state.take(bag, shape)?;
+        }
+        Some(Position { board, hold, just_held: false, queue: seen.iter().copied().collect(), queue_state, revealed })
+    }
+
+    /// The position right after `action`, before anything new is revealed
+    pub fn after(&self, action: Move) -> Position {
+        let mut after = self.clone();
+        let current = after.queue.pop_front();
+        after.board = action.board(self.board);
+        after.just_held = matches!(action, Move::Hold(_));
+        if !matches!(action, Move::Place(..)) {
+            after.hold = current;
+        }
+        after
+    }
+
+    /// Whether a perfect clear is done
+    pub fn is_done(&self, two_line: bool) -> bool {
+        self.board == Board::full() || (two_line && self.board == Board::half())
+    }
+}
+
+impl Search<'_> {
+    /// Every legal move from `position`, best first.  Moves with the same
+    /// chance keep the order they're found in: placing, then holding, then
+    /// swapping.
+    pub fn moves(&self, position: &Position) -> Vec<Advice> {
+        let (current, edges) = match (position.queue.front(), self.gigapan.edges(position.board)) {
+            (Some(&current), Some(edges)) => (current, edges),
+            _ => return Vec::new(),
+        };
+        let allowed = |board: &Board| match self.culled {
+            Some(culled) => culled.contains(board),
+            None => true,
+        };
+
+        let mut actions: Vec<Move> = edges.get(current).filter(allowed).map(|board| Move::Place(current, board)).collect();
+        if self.use_hold && !position.just_held {
+            match position.hold {
+                // the last piece can't be held, as nothing would come out instead
+                None if position.queue.len() > 1 || position.revealed < self.counted_bags.len() => {
+                    actions.push(Move::Hold(current));
+                }
+                Some(held) if held != current => {
+                    actions.extend(edges.get(held).filter(allowed).map(|board| Move::Swap(held, board)));
+                }
+                _ => {}
+            }
+        }
+
+        let total = self.total(position);
+        let mut moves: Vec<Advice> = actions
+            .into_par_iter()
+            .map_init(Feasibility::new, |feasibility, action| Advice {
+                action,
+                passing: self.passing(feasibility, &position.after(action)),
+                total,
+            })
+            .collect();
+        moves.sort_by_key(|advice| std::cmp::Reverse(advice.passing));
+        moves
+    }
+
+    /// Hidden queues from `position` on
+    pub fn total(&self, position: &Position) -> usize {
+        count_queues(self.counted_bags, position.queue_state, position.revealed, self.counted_bags.len())
+    }
+
+    /// The positions once the next piece is revealed after a move, by that
+    /// piece, or just the same position once every piece is revealed
+    pub fn reveal(&self, after: &Position) -> Vec<(Option<Shape>, Position)> {
+        if after.revealed >= self.counted_bags.len() {
+            return vec![(None, after.clone())];
+        }
+
+        let (bag_placement, bag) = &self.counted_bags[after.revealed];
+        let queue_state = if bag_placement == &0 { after.queue_state.next(bag) } else { after.queue_state };
+        Shape::ALL
+            .into_iter()
+            .filter_map(|shape| {
+                let mut next = after.clone();
+                next.queue_state = queue_state.take(bag, shape)?;
+                next.queue.push_back(shape);
+                next.revealed += 1;
+                Some((Some(shape), next))
+            })
+            .collect()
+    }
+
+    /// Hidden queues which pass after a move, as [`max_limited_see_queues`]
+    /// counts them from a position before a reveal
+    fn passing(&self, feasibility: &mut Feasibility, after: &Position) -> usize {
+        self.reveal(after)
+            .into_iter()
+            .map(|(_, mut next)| {
+                max_limited_see_queues(self, feasibility, next.board, next.hold, next.just_held, next.queue_state, &mut next.queue, next.revealed).0
+            })
+            .sum()
+    }
+}
+
+/// Rank every move from `board` with the first pieces of `combinatoric_queue`
+/// seen, by the limited see chance after it
+pub fn advise(
+    gigapan: &FrozenGigapan,
+    board: Board,
+    combinatoric_queue: &CombinatoricQueue,
+    seen: &[Shape],
+    use_hold: bool,
+    culled: Option<&HashSet<Board>>,
+    two_line: bool,
+) -> Result<Advised, String> {
+    if seen.is_empty() {
+        return Err("the current piece has to be seen".to_string());
+    }
+    if !fills_board(board, combinatoric_queue) {
+        return Err(format!("{combinatoric_queue} doesn't have the right number of pieces for the board"));
+    }
+    if gigapan.edges(board).is_none() {
+        return Err(format!("no perfect clear is possible from {board}"));
+    }
+    let position = Position::new(board, combinatoric_queue, seen)?;
+
+    let counted_bags = combinatoric_queue.get_counted_bags();
+    let memo = LimitedSeeMemo::new();
+    let search = Search { gigapan, culled, memo: &memo, counted_bags: &counted_bags, use_hold, two_line };
+
+    Ok(Advised {
+        board,
+        hold: position.hold,
+        seen: seen.to_vec(),
+        pattern: combinatoric_queue.to_string(),
+        use_hold,
+        culled: culled.is_some(),
+        two_line,
+        moves: search.moves(&position),
+    })
+}
+
+impl Advised {
+    /// Write one row per move, best first
+    pub fn write(&self, format: OutputFormat, mut to: impl Write) -> std::io::Result<()> {
+        let board = encode_boards([(self.board, None)]);
+        let seen: String = self.seen.iter().map(|shape| shape.name()).collect();
+        let hold = self.hold.map_or("", |shape| shape.name());
+        let rows = self.moves.iter().map(|advice| {
+            let after = advice.action.board(self.board);
+            let fumen = match advice.action.placed() {
+                Some(shape) => encode_path(self.board, None, [(shape, after, Some(advice.action.to_string()))]),
+                None => encode_boards([(after, Some(advice.action.to_string()))]),
+            };
+            (advice, fumen)
+        });
+
+        match format {
+            OutputFormat::Text => {
+                writeln!(to, "{:>4} {:>14} {:>21} {:>8}  fumen", "rank", "move", "passing", "chance")?;
+                for (rank, (advice, fumen)) in rows.enumerate() {
+                    let passing = format!("{}/{}", advice.passing, advice.total);
+                    writeln!(to, "{:>4} {:>14} {:>21} {:>7.2}%  {fumen}", rank + 1, advice.action.to_string(), passing, advice.chance())?;
+                }
+            }
+            OutputFormat::Json => {
+                let moves: Vec<serde_json::Value> = rows
+                    .map(|(advice, fumen)| {
+                        serde_json::json!({
+                            "move": advice.action.to_string(),
+                            "hold": !matches!(advice.action, Move::Place(..)),
+                            "placed": advice.action.placed().map(|shape| shape.name()),
+                            "fumen": fumen,
+                            "passing": advice.passing,
+                            "total": advice.total,
+                            "chance": advice.chance(),
+                        })
+                    })
+                    .collect();
+                serde_json::to_writer(&mut to, &serde_json::json!({
+                    "inputs": {
+                        "board": board,
+                        "hold": self.hold.map(|shape| shape.name()),
+                        "seen": seen,
+                        "pattern": self.pattern,
+                        "use_hold": self.use_hold,
+                        "culled": self.culled,
+                        "two_line": self.two_line,
+                    },
+                    "moves": moves,
+                }))?;
+                writeln!(to)?;
+            }
+            OutputFormat::Csv => {
+                writeln!(to, "# board: {board}")?;
+                writeln!(to, "# hold: {hold}")?;
+                writeln!(to, "# seen: {seen}")?;
+                writeln!(to, "# pattern: {}", self.pattern)?;
+                writeln!(to, "# use_hold: {}", self.use_hold)?;
+                writeln!(to, "# culled: {}", self.culled)?;
+                writeln!(to, "# two_line: {}", self.two_line)?;
+                writeln!(to, "rank,move,passing,total,chance,fumen")?;
+                for (rank, (advice, fumen)) in rows.enumerate() {
+                    writeln!(to, "{},{},{},{},{},{fumen}", rank + 1, advice.action, advice.passing, advice.total, advice.chance())?;
+                }
+            }
+        }
+        to.flush()
+    }
+}
+
+#[test]
+fn best_move_matches_search() {
+    use std::str::FromStr;
+    use Shape::*;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let mut queue = CombinatoricQueue::from_str("*p6").unwrap();
+    queue.set_hold(O);
+    let seen = [T, L];
+
+    let advised = advise(&gigapan, board, &queue, &seen, true, None, false).unwrap();
+    assert!(advised.moves.iter().any(|advice| matches!(advice.action, Move::Place(T, _))));
+    assert!(advised.moves.iter().any(|advice| matches!(advice.action, Move::Swap(O, _))));
+    assert!(advised.moves.windows(2).all(|pair| pair[0].passing >= pair[1].passing));
+
+    // the best move gets what the search gets for the whole position
+    let counted_bags = queue.get_counted_bags();
+    let mut queue_state = QueueState(counted_bags[0].1.full);
+    for ((i, bag), shape) in counted_bags.iter().zip([O, T, L]) {
+        let state = if i == &0 { queue_state.next(bag) } else { queue_state };
+        queue_state = state.take(bag, shape).unwrap();
+    }
+    let memo = LimitedSeeMemo::new();
+    let search = Search { gigapan: &gigapan, culled: None, memo: &memo, counted_bags: &counted_bags, use_hold: true, two_line: false };
+    let (expected, _) = max_limited_see_queues(&search, &mut Feasibility::new(), board, Some(O), false, queue_state, &mut VecDeque::from(seen), 3);
+    assert_eq!(advised.moves[0].passing, expected);
+    assert_eq!(expected, 12);
+    assert_eq!(advised.moves[0].total, 120);
+
+    assert!(advise(&gigapan, board, &queue, &[T, T], true, None, false).is_err());
+}
@@ -0,0 +1,276 @@
+//! Many chance runs against one loaded graph, read from a file with one JSON
+//! job per line, so the shards only have to be loaded once.
+
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use legal_boards::boardgraph::{FrozenGigapan, GigapanLookup};
+use serde::Deserialize;
+use srs_4l::gameplay::Board;
+
+use crate::calculate::{fills_board, limited_see_chance_watched, ChanceOptions, ChanceResult, OutputFormat, Progress, Stop};
+use crate::fumens::{decode_fumen, encode_boards};
+use crate::queue::{parse_shape, CombinatoricQueue};
+
+/// One line of a batch file.  Only `pattern` is required, like
+/// `{"fumen": "v115@vhAAgH", "pattern": "*p7", "previews": 3}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// Shown with the results to tell jobs apart
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default = "empty_board")]
+    pub fumen: String,
+    pub pattern: String,
+    #[serde(default = "five")]
+    pub previews: usize,
+    /// Whether hold can be used
+    #[serde(default = "yes")]
+    pub hold: bool,
+    /// Start off with no piece in hold
+    #[serde(default)]
+    pub blank_start: bool,
+    /// Start off with this piece in hold, like `(T)` in the pattern
+    #[serde(default)]
+    pub start_hold: Option<String>,
+    /// 4, or 2 to also count 2-line PCs
+    #[serde(default = "four")]
+    pub lines: u8,
+    #[serde(default)]
+    pub culled: bool,
+    /// Stop after this many seconds with bounds on the chance
+    #[serde(default)]
+    pub time_limit: Option<f64>,
+    /// Stop once the bounds on the chance are within this many percent
+    #[serde(default)]
+    pub gap: Option<f64>,
+}
+
+pub(crate) fn empty_board() -> String {
+    "v115@vhAAgH".to_string()
+}
+
+fn five() -> usize {
+    5
+}
+
+pub(crate) fn yes() -> bool {
+    true
+}
+
+fn four() -> u8 {
+    4
+}
+
+impl Job {
+    pub fn board(&self) -> Result<Board, String> {
+        decode_fumen(&self.fumen)
+            .map(Board)
+            .ok_or_else(|| format!("{} is not a fumen of the bottom 4 rows", self.fumen))
+    }
+
+    pub fn queue(&self) -> Result<CombinatoricQueue, String> {
+        let mut queue =
+            CombinatoricQueue::from_str(&self.pattern).map_err(|_| format!("{} is not a valid pattern", self.pattern))?;
+        if let Some(hold) = &self.start_hold {
+            queue.set_hold(parse_shape(hold)?);
+        }
+        Ok(queue)
+    }
+
+    fn two_line(&self) -> Result<bool, String> {
+        match self.lines {
+            4 => Ok(false),
+            2 => Ok(true),
+            lines => Err(format!("{lines} lines isn't supported, only 2 or 4")),
+        }
+    }
+
+    /// When the job may stop early
+    pub fn stop(&self) -> Result<Stop, String> {
+        let time = match self.time_limit {
+            Some(seconds) => Some(Duration::try_from_secs_f64(seconds).map_err(|_| format!("{seconds} isn't a time limit in seconds"))?),
+            None => None,
+        };
+        Ok(Stop { time, gap: self.gap })
+    }
+
+    /// Run the job, reporting to `progress`.  A cancelled job is an error.
+    /// `progress` should be made with the job's [`stop`](Self::stop).
+    pub fn run(&self, gigapan: &FrozenGigapan, progress: &Progress) -> Result<ChanceResult, String> {
+        let board = self.board()?;
+        let queue = self.queue()?;
+        let two_line = self.two_line()?;
+        self.stop()?;
+        if gigapan.edges(board).is_none() {
+            return Err(format!("no perfect clear is possible from {}", self.fumen));
+        }
+        let init_hold = !self.blank_start || queue.hold().is_some();
+        let most = queue.get_counted_bags().len() - 1 - init_hold as usize;
+        if self.previews > most {
+            return Err(format!("the pattern only allows up to {most} previews"));
+        }
+        if !fills_board(board, &queue) {
+            return Err(format!("{} doesn't have the right number of pieces for the board", self.pattern));
+        }
+        let options = ChanceOptions {
+            previews: self.previews,
+            init_hold,
+            use_hold: self.hold,
+            culled: self.culled,
+            two_line,
+            checkpoint: None,
+            progress: Some(progress),
+        };
+        limited_see_chance_watched(gigapan, board, &queue, &options)
+            .ok_or_else(|| "cancelled".to_string())
+    }
+}
+
+/// Read one job per line, skipping blank lines and lines starting with `#`
+pub fn read_jobs(reader: impl BufRead) -> Result<Vec<Job>, String> {
+    let mut jobs = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|error| error.to_string())?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let job: Job = serde_json::from_str(line).map_err(|error| format!("line {}: {error}", number + 1))?;
+        job.stop().map_err(|error| format!("line {}: {error}", number + 1))?;
+        jobs.push(job);
+    }
+    Ok(jobs)
+}
+
+/// Run every job in order, writing each result as soon as it's done.  A job
+/// which can't run is written with its error instead.  Returns how many jobs
+/// failed.
+pub fn run_jobs(gigapan: &FrozenGigapan, jobs: &[Job], format: OutputFormat, mut to: impl Write) -> std::io::Result<usize> {
+    let mut failed = 0;
+    if format == OutputFormat::Csv {
+        writeln!(
+            to,
+            "job,name,board,pattern,previews,init_hold,use_hold,culled,two_line,passing,upper,complete,total,chance,seconds,error"
+        )?;
+    }
+
+    for (index, job) in jobs.iter().enumerate() {
+        eprintln!("job {}/{}: {} {}", index + 1, jobs.len(), job.fumen, job.pattern);
+        let progress = Progress::with_stop(job.stop().unwrap_or_default());
+        let result = job.run(gigapan, &progress);
+        if let Err(error) = &result {
+            eprintln!("job {} failed: {error}", index + 1);
+            failed += 1;
+        }
+        let name = job.name.clone().unwrap_or_default();
+
+        match (format, result) {
+            (OutputFormat::Text, Ok(result)) if !result.complete => {
+                writeln!(
+                    to,
+                    "{name} {} {}: {} to {} of {} ({:.2}% to {:.2}%, stopped early)",
+                    job.fumen,
+                    result.pattern,
+                    result.passing,
+                    result.upper,
+                    result.total,
+                    result.chance(),
+                    result.upper_chance()
+                )?;
+            }
+            (OutputFormat::Text, Ok(result)) => {
+                writeln!(to, "{name} {} {}: {}/{} ({:.2}%)", job.fumen, result.pattern, result.passing, result.total, result.chance())?;
+            }
+            (OutputFormat::Text, Err(error)) => {
+                writeln!(to, "{name} {} {}: {error}", job.fumen, job.pattern)?;
+            }
+            (OutputFormat::Json, Ok(result)) => {
+                let mut summary = result.summary();
+                summary["job"] = index.into();
+                summary["name"] = job.name.clone().into();
+                serde_json::to_writer(&mut to, &summary)?;
+                writeln!(to)?;
+            }
+            (OutputFormat::Json, Err(error)) => {
+                serde_json::to_writer(&mut to, &serde_json::json!({
+                    "job": index,
+                    "name": job.name,
+                    "error": error,
+                }))?;
+                writeln!(to)?;
+            }
+            (OutputFormat::Csv, Ok(result)) => {
+                writeln!(
+                    to,
+                    "{index},{},{},{},{},{},{},{},{},{},{},{},{},{},{},",
+                    csv_field(&name),
+                    encode_boards([(result.board, None)]),
+                    csv_field(&result.pattern),
+                    result.previews,
+                    result.init_hold,
+                    result.use_hold,
+                    result.culled,
+                    result.two_line,
+                    result.passing,
+                    result.upper,
+                    result.complete,
+                    result.total,
+                    result.chance(),
+                    result.elapsed.as_secs_f64()
+                )?;
+            }
+            (OutputFormat::Csv, Err(error)) => {
+                writeln!(to, "{index},{},{},{},,,,,,,,,,,,{}", csv_field(&name), csv_field(&job.fumen), csv_field(&job.pattern), csv_field(&error))?;
+            }
+        }
+        // keep what's done if a later job is interrupted
+        to.flush()?;
+    }
+    Ok(failed)
+}
+
+/// Quote a field which could hold commas, as patterns do
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[test]
+fn jobs() {
+    let file = r#"
+# the left two columns of the bottom two rows are empty
+{"fumen": "v115@ThH8BeH8JeAgH", "pattern": "O,*p6", "previews": 1, "name": "hold"}
+{"fumen": "v115@ThH8BeH8JeAgH", "pattern": "O,*p6", "previews": 1, "hold": false}
+
+{"fumen": "v115@ThH8BeH8JeAgH", "pattern": "*p3"}
+"#;
+    let jobs = read_jobs(file.as_bytes()).unwrap();
+    assert_eq!(jobs.len(), 3);
+    assert_eq!(jobs[0].name.as_deref(), Some("hold"));
+    assert!(jobs[1].lines == 4 && !jobs[1].hold);
+    assert!(read_jobs(r#"{"pattern": "*p7", "preview": 3}"#.as_bytes()).unwrap_err().starts_with("line 1:"));
+
+    let board = jobs[0].board().unwrap();
+    let gigapan = legal_boards::boardgraph::subgraph(&[board]).freeze();
+    let mut output = Vec::new();
+    let failed = run_jobs(&gigapan, &jobs, OutputFormat::Json, &mut output).unwrap();
+    assert_eq!(failed, 1);
+
+    let results: Vec<serde_json::Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0]["passing"], 600);
+    assert_eq!(results[0]["name"], "hold");
+    assert_eq!(results[1]["passing"], 0);
+    assert_eq!(results[1]["inputs"]["use_hold"], false);
+    assert!(results[2]["error"].is_string());
+}
@@ -1,20 +1,23 @@
 use std::collections::VecDeque;
 use std::fmt::Write as FmtWrite;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use legal_boards::boardgraph::FrozenGigapan;
-use crate::queue::{Bag, QueueState, get_queue_permutations, CombinatoricQueue};
+use legal_boards::boardgraph::{FrozenGigapan, GigapanLookup};
+use crate::checkpoint::Checkpoint;
+use crate::fumens::encode_boards;
+use crate::queue::{Bag, QueueState, count_queues, unrevealed_shapes, CombinatoricQueue};
+use crate::solutions::Step;
 use hashbrown::HashSet;
 use compute::ShardedHashMap;
 
 use rayon::prelude::{IntoParallelIterator, ParallelIterator, IntoParallelRefMutIterator};
-use srs_4l::gameplay::{Board, Shape};
+use srs_4l::{feasibility::Feasibility, gameplay::{Board, Shape}};
 
 type NoHashBuilder = nohash::BuildNoHashHasher<u64>;
 type ScanStage = ShardedHashMap<Board, (Vec<QueueState>, Vec<Board>), 20, NoHashBuilder>;
 
-use std::fs::File;
-use std::io::{Write, LineWriter};
+use std::io::Write;
 
 pub fn limited_see_chance(
     gigapan: &FrozenGigapan,
@@ -25,72 +28,446 @@ pub fn limited_see_chance(
     use_hold: bool,
     generate_culled: bool,
     two_line: bool
-) {
-    let counted_bags = &combinatoric_queue.get_counted_bags();
+) -> Option<ChanceResult> {
+    let options = ChanceOptions { previews, init_hold, use_hold, culled: generate_culled, two_line, ..ChanceOptions::default() };
+    limited_see_chance_watched(gigapan, board, combinatoric_queue, &options)
+}
 
-    let piece_count: usize = counted_bags.len()-1;
-    let new_mino_count = piece_count as u32 * 4;
-    if board.0.count_ones() + new_mino_count != 40 {
+/// The settings of a chance run
+#[derive(Clone, Copy, Default)]
+pub struct ChanceOptions<'a> {
+    /// Pieces seen after the current one
+    pub previews: usize,
+    /// Whether the first piece starts in hold
+    pub init_hold: bool,
+    pub use_hold: bool,
+    /// Only search boards on the way to a perfect clear with the pattern
+    pub culled: bool,
+    pub two_line: bool,
+    /// Revealed queues already done are skipped, and the rest are written to it
+    pub checkpoint: Option<&'a Checkpoint>,
+    /// Where the run reports how far it has got
+    pub progress: Option<&'a Progress>,
+}
+
+/// [`limited_see_chance`] which reports to `options.progress`, and gives up
+/// with `None` once it is cancelled.  When it stops early by the rules of the
+/// progress, the result is incomplete and gives the bounds found so far.
+/// Revealed queues already in `options.checkpoint` are skipped, and the rest
+/// are written to it as they're done.
+pub fn limited_see_chance_watched(
+    gigapan: &FrozenGigapan,
+    board: Board,
+    combinatoric_queue: &CombinatoricQueue,
+    options: &ChanceOptions
+) -> Option<ChanceResult> {
+    let instant = Instant::now();
+    if !fills_board(board, combinatoric_queue){
         eprintln!("bad queue len");
-        return;
+        return None;
     }
-    let culled = if generate_culled{
-        let instant = Instant::now();
-        let c = get_culled_boards(&gigapan, board, counted_bags, use_hold);
-        eprintln!("found {} total possible path boards in {:?}", c.len(), instant.elapsed());
-        Some(c)
+    let unwatched = Progress::default();
+    let progress = options.progress.unwrap_or(&unwatched);
+    let culled = if options.culled{
+        Some(culled_boards(gigapan, board, combinatoric_queue, options.use_hold))
     }else{
         None
     };
-    let culled = culled.as_ref();
 
-    let permutations = get_queue_permutations(counted_bags, None, Some(previews+1+init_hold as usize));
+    if progress.is_cancelled(){
+        return None;
+    }
+
+    let options = ChanceOptions { progress: Some(progress), ..*options };
+    let mut result = limited_see_chance_with(gigapan, board, combinatoric_queue, &options, culled.as_ref(), &LimitedSeeMemo::new());
+    if progress.is_cancelled(){
+        return None;
+    }
+    result.elapsed = instant.elapsed();
+    Some(result)
+}
+
+/// When a chance run may stop early with the bounds found so far
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stop {
+    /// Stop once the run has taken this long
+    pub time: Option<Duration>,
+    /// Stop once the upper and lower bounds are within this many percent
+    pub gap: Option<f64>,
+}
+
+impl Stop {
+    pub fn is_set(&self) -> bool {
+        self.time.is_some() || self.gap.is_some()
+    }
+}
+
+/// How far a chance run has got, shared with other threads so they can watch
+/// it or cancel it
+#[derive(Debug, Default)]
+pub struct Progress {
+    done: AtomicUsize,
+    total: AtomicUsize,
+    lower: AtomicUsize,
+    upper: AtomicUsize,
+    cancelled: AtomicBool,
+    stopped: AtomicBool,
+    stop: Stop,
+}
+
+impl Progress {
+    /// Progress of a run which stops early by `stop`
+    pub fn with_stop(stop: Stop) -> Self {
+        Progress { stop, ..Progress::default() }
+    }
+
+    /// Revealed queues evaluated so far
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// Revealed queues to evaluate, or 0 before the run starts on them
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    /// Stop the run as soon as possible.  Its results will be incomplete.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Queues of the pattern known to pass so far
+    pub fn lower(&self) -> usize {
+        self.lower.load(Ordering::Relaxed)
+    }
+
+    /// Queues of the pattern which could still pass
+    pub fn upper(&self) -> usize {
+        self.upper.load(Ordering::Relaxed)
+    }
+
+    /// Whether the run stopped early by its [`Stop`]
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether the pattern places exactly enough pieces to fill the board, with
+/// one left over
+pub(crate) fn fills_board(board: Board, combinatoric_queue: &CombinatoricQueue) -> bool{
+    let piece_count: usize = combinatoric_queue.get_counted_bags().len()-1;
+    let new_mino_count = piece_count as u32 * 4;
+    board.0.count_ones() + new_mino_count == 40
+}
+
+/// The boards on the way to a perfect clear with the pattern, for `culled`
+pub fn culled_boards(gigapan: &FrozenGigapan, board: Board, combinatoric_queue: &CombinatoricQueue, use_hold: bool) -> HashSet<Board>{
+    let instant = Instant::now();
+    let culled = get_culled_boards(gigapan, board, &combinatoric_queue.get_counted_bags(), use_hold);
+    eprintln!("found {} total possible path boards in {:?}", culled.len(), instant.elapsed());
+    culled
+}
+
+/// [`limited_see_chance`] for a pattern which fills the board, with the culled
+/// boards already found in place of `options.culled`.  Runs with the same hold
+/// settings, culling and two_line can share `memo`, whatever their previews.
+///
+/// The bounds in the progress start from every queue of the pattern.  Each
+/// revealed queue which is done raises the lower bound by its passing queues.
+/// If the run may stop early, every full queue is first solved seeing the
+/// whole queue, which is much cheaper, and the ones which fail lower the
+/// upper bound until their revealed queue is done.
+pub(crate) fn limited_see_chance_with(
+    gigapan: &FrozenGigapan,
+    board: Board,
+    combinatoric_queue: &CombinatoricQueue,
+    options: &ChanceOptions,
+    culled: Option<&HashSet<Board>>,
+    memo: &LimitedSeeMemo
+) -> ChanceResult {
+    let instant = Instant::now();
+    let &ChanceOptions { previews, init_hold, use_hold, two_line, checkpoint, .. } = options;
+    let unwatched = Progress::default();
+    let progress = options.progress.unwrap_or(&unwatched);
+    let counted_bags = &combinatoric_queue.get_counted_bags();
+    // a known hold is the first piece, so it must start in hold
+    let init_hold = init_hold || combinatoric_queue.hold().is_some();
+
+    let revealed = previews+1+init_hold as usize;
+    let root_state = QueueState(counted_bags.first().unwrap().1.full);
      
-    let bar = indicatif::ProgressBar::new(permutations.len() as u64);
+    let total = count_queues(counted_bags, root_state, 0, revealed);
+    progress.done.store(0, Ordering::Relaxed);
+    progress.total.store(total, Ordering::Relaxed);
+    progress.lower.store(0, Ordering::Relaxed);
+    progress.upper.store(combinatoric_queue.queue_count(), Ordering::Relaxed);
+    progress.stopped.store(false, Ordering::Relaxed);
+    let bar = indicatif::ProgressBar::new(total as u64);
     bar.set_style(indicatif::ProgressStyle::with_template("{spinner:.green} [{elapsed_precise}] [{bar:.cyan/blue}] {pos}/{human_len} queues ({eta})")
     .unwrap()
     .with_key("eta", |state: &indicatif::ProgressState, w: &mut dyn FmtWrite| write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap())
     .progress_chars("#>-"));
 
-    let fails : Vec<_>= permutations.into_par_iter().map(|mut queue|{
-        let mut start_queue_state = QueueState(counted_bags.first().unwrap().1.full);
-        for ((i, bag), shape) in counted_bags.iter().zip(queue.iter()){
-            let s = if i == &0 { start_queue_state.next(bag) } else { start_queue_state };
-            start_queue_state = s.take(bag, *shape).unwrap();
+    let mut walk = PrefixWalk{
+        search: Search{gigapan, culled, memo, counted_bags, use_hold, two_line},
+        board,
+        init_hold,
+        revealed,
+        bar: &bar,
+        progress,
+        checkpoint,
+        deadline: progress.stop.time.map(|time| instant + time),
+        queue_count: combinatoric_queue.queue_count(),
+        full_see: None,
+    };
+    if progress.stop.is_set(){
+        walk.full_see = walk.full_see(0, root_state, Some(vec![(board, None)]));
+        eprintln!("full see bounds: {}/{}", progress.upper(), walk.queue_count);
+    }
+    if let Some(checkpoint) = checkpoint.filter(|checkpoint| !checkpoint.is_empty()){
+        eprintln!("resuming with {}/{total} queues done", checkpoint.len());
+    }
+    let prefixes = walk.walk(&mut Feasibility::new(), &mut VecDeque::new(), 0, root_state, None, walk.full_see.as_ref()).unwrap_or_default();
+
+    bar.finish_and_clear();
+
+    let complete = progress.done() == total;
+    let passing = prefixes.passing;
+    eprintln!("computed in: {:.3}s",bar.elapsed().as_secs_f64());
+
+    ChanceResult{
+        board,
+        pattern: combinatoric_queue.to_string(),
+        previews,
+        init_hold,
+        use_hold,
+        culled: culled.is_some(),
+        two_line,
+        passing,
+        upper: if complete {passing} else {progress.upper()},
+        complete,
+        total: combinatoric_queue.queue_count(),
+        prefixes,
+        elapsed: instant.elapsed(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+/// Passing and total full queues starting with a prefix of the pattern, with
+/// the prefixes a piece longer down to the revealed queues.  A prefix which
+/// fails as a whole isn't extended, and ones a stopped run didn't get to are
+/// left out of the counts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefixNode {
+    pub passing: usize,
+    pub total: usize,
+    pub next: Vec<(Shape, PrefixNode)>,
+}
+
+impl PrefixNode {
+    fn add(&mut self, shape: Shape, node: PrefixNode) {
+        self.passing += node.passing;
+        self.total += node.total;
+        self.next.push((shape, node));
+    }
+
+    /// Every node below this one, with its prefix after `prefix`, before its
+    /// extensions and in the order of [`Shape::ALL`]
+    fn visit(&self, prefix: &mut VecDeque<Shape>, depth: usize, visit: &mut impl FnMut(&VecDeque<Shape>, &PrefixNode)) {
+        if depth == 0{
+            return
         }
-        let revealed_pieces = queue.len();
-        let hold = if init_hold{
-            Some(queue.pop_front().unwrap())
-        }else{
-            None
-        };
+        for (shape, node) in &self.next{
+            prefix.push_back(*shape);
+            visit(prefix, node);
+            node.visit(prefix, depth - 1, visit);
+            prefix.pop_back();
+        }
+    }
+}
 
-        let passed = max_limited_see_queues(gigapan, culled, board, hold, use_hold, false, two_line, counted_bags, start_queue_state, &mut queue, revealed_pieces);
-        if let Some(hold) = hold{queue.push_front(hold);}
-        bar.inc(1);
-        (queue, passed)
-    }).collect();
+/// Outcome of a limited see chance run, along with what was run
+pub struct ChanceResult {
+    pub board: Board,
+    pub pattern: String,
+    pub previews: usize,
+    /// Whether simulations start with the first piece in hold
+    pub init_hold: bool,
+    pub use_hold: bool,
+    pub culled: bool,
+    pub two_line: bool,
+    /// Number of queues which achieve a perfect clear, or at least achieve it
+    /// if the run stopped early
+    pub passing: usize,
+    /// Most queues which could achieve a perfect clear, the same as `passing`
+    /// unless the run stopped early
+    pub upper: usize,
+    /// Whether every revealed queue was evaluated
+    pub complete: bool,
+    /// Number of queues the pattern can produce
+    pub total: usize,
+    /// The prefix tree of the queues evaluated
+    pub prefixes: PrefixNode,
+    pub elapsed: Duration,
+}
 
-    bar.finish_and_clear();
+impl ChanceResult {
+    pub fn chance(&self) -> f64 {
+        self.passing as f64 / self.total as f64 * 100.0
+    }
 
-    let file = File::create("fail-queues.txt").unwrap();
-    let mut file = LineWriter::new(file);
+    /// The highest the chance could be, the same as [`chance`](Self::chance)
+    /// unless the run stopped early
+    pub fn upper_chance(&self) -> f64 {
+        self.upper as f64 / self.total as f64 * 100.0
+    }
 
-    let mut total = 0;
-    for (fail, (covered, maximum)) in fails{
-        total += covered;
-        let maximum = maximum.unwrap_or(0);
-        if covered != maximum{
-            file.write_fmt(format_args!("{:?} {} {}\n", fail, covered, maximum)).unwrap();
+    /// The chance as a fraction in lowest terms
+    pub fn fraction(&self) -> (usize, usize) {
+        let (mut a, mut b) = (self.passing, self.total);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        match a {
+            0 => (self.passing, self.total),
+            gcd => (self.passing / gcd, self.total / gcd),
         }
     }
-    let total_queues = combinatoric_queue.queue_count();
-    println!("passing queues: {total}/{}",total_queues);
-    println!("chance: {}%", total as f64 / total_queues as f64 * 100.0);
-    eprintln!("computed in: {:.3}s",bar.elapsed().as_secs_f64());
 
+    /// Passing and total queues for every prefix of up to `depth` pieces, like
+    /// sfinder's percent tree.  Prefixes come before their extensions, and
+    /// ones which fail as a whole have none.
+    pub fn tree(&self, depth: usize) -> Vec<(String, usize, usize)> {
+        let mut tree = Vec::new();
+        self.prefixes.visit(&mut VecDeque::new(), depth, &mut |prefix, node|{
+            tree.push((prefix.iter().map(|shape| shape.name()).collect(), node.passing, node.total));
+        });
+        tree
+    }
 
-    
+    /// Every revealed queue, and every shorter prefix which fails as a whole,
+    /// with how many of the full queues starting with it pass and how many
+    /// there are
+    pub fn queues(&self) -> Vec<(VecDeque<Shape>, usize, usize)> {
+        let mut queues = Vec::new();
+        self.prefixes.visit(&mut VecDeque::new(), usize::MAX, &mut |prefix, node|{
+            if node.next.is_empty(){
+                queues.push((prefix.clone(), node.passing, node.total));
+            }
+        });
+        queues
+    }
+
+    /// The inputs and chance as json, without the queues
+    pub fn summary(&self) -> serde_json::Value {
+        let (numerator, denominator) = self.fraction();
+        serde_json::json!({
+            "inputs": {
+                "board": encode_boards([(self.board, None)]),
+                "pattern": self.pattern,
+                "previews": self.previews,
+                "init_hold": self.init_hold,
+                "use_hold": self.use_hold,
+                "culled": self.culled,
+                "two_line": self.two_line,
+            },
+            "passing": self.passing,
+            "upper": self.upper,
+            "complete": self.complete,
+            "total": self.total,
+            "fraction": [numerator, denominator],
+            "chance": self.chance(),
+            "upper_chance": self.upper_chance(),
+            "seconds": self.elapsed.as_secs_f64(),
+        })
+    }
+
+    /// Write the report, with a percent tree `tree_depth` pieces deep if it isn't 0
+    pub fn write(&self, format: OutputFormat, tree_depth: usize, mut to: impl Write) -> std::io::Result<()> {
+        let tree = self.tree(tree_depth);
+        let percent = |passing: usize, total: usize| passing as f64 / total as f64 * 100.0;
+        let queues = self.queues().into_iter().map(|(queue, covered, maximum)|{
+            (queue.iter().map(|shape| shape.name()).collect::<String>(), covered, maximum)
+        });
+        let (numerator, denominator) = self.fraction();
+        let board = encode_boards([(self.board, None)]);
+        let seconds = self.elapsed.as_secs_f64();
+
+        match format {
+            OutputFormat::Text if !self.complete => {
+                writeln!(to, "stopped early, the queues shown are the ones evaluated")?;
+                writeln!(to, "passing queues: {} to {} of {}", self.passing, self.upper, self.total)?;
+                writeln!(to, "chance: {}% to {}%", self.chance(), self.upper_chance())?;
+                for (queue, covered, maximum) in queues.filter(|(_, covered, maximum)| covered != maximum){
+                    writeln!(to, "{queue} {covered} {maximum}")?;
+                }
+            }
+            OutputFormat::Text => {
+                writeln!(to, "passing queues: {}/{}", self.passing, self.total)?;
+                writeln!(to, "chance: {}%", self.chance())?;
+                for (prefix, passing, total) in &tree{
+                    let indent = 2 * (prefix.len() - 1);
+                    writeln!(to, "{:indent$}{prefix}: {:.2}% ({passing}/{total})", "", percent(*passing, *total))?;
+                }
+                for (queue, covered, maximum) in queues.filter(|(_, covered, maximum)| covered != maximum){
+                    writeln!(to, "{queue} {covered} {maximum}")?;
+                }
+            }
+            OutputFormat::Json => {
+                let queues: Vec<serde_json::Value> = queues.map(|(queue, covered, maximum)|{
+                    serde_json::json!({"queue": queue, "covered": covered, "maximum": maximum})
+                }).collect();
+                let tree: Vec<serde_json::Value> = tree.iter().map(|(prefix, passing, total)|{
+                    serde_json::json!({"prefix": prefix, "passing": passing, "total": total, "chance": percent(*passing, *total)})
+                }).collect();
+                let mut summary = self.summary();
+                summary["queues"] = queues.into();
+                summary["tree"] = tree.into();
+                serde_json::to_writer(&mut to, &summary)?;
+                writeln!(to)?;
+            }
+            OutputFormat::Csv => {
+                // run-wide values as comment lines, so the table stays rectangular
+                writeln!(to, "# board: {board}")?;
+                writeln!(to, "# pattern: {}", self.pattern)?;
+                writeln!(to, "# previews: {}", self.previews)?;
+                writeln!(to, "# init_hold: {}", self.init_hold)?;
+                writeln!(to, "# use_hold: {}", self.use_hold)?;
+                writeln!(to, "# culled: {}", self.culled)?;
+                writeln!(to, "# two_line: {}", self.two_line)?;
+                writeln!(to, "# passing: {}", self.passing)?;
+                writeln!(to, "# upper: {}", self.upper)?;
+                writeln!(to, "# complete: {}", self.complete)?;
+                writeln!(to, "# total: {}", self.total)?;
+                writeln!(to, "# fraction: {numerator}/{denominator}")?;
+                writeln!(to, "# chance: {}", self.chance())?;
+                writeln!(to, "# seconds: {seconds}")?;
+                writeln!(to, "queue,covered,maximum")?;
+                for (queue, covered, maximum) in queues{
+                    writeln!(to, "{queue},{covered},{maximum}")?;
+                }
+                // the percent tree is a second table after a blank line
+                if !tree.is_empty(){
+                    writeln!(to)?;
+                    writeln!(to, "prefix,passing,total,chance")?;
+                    for (prefix, passing, total) in &tree{
+                        writeln!(to, "{prefix},{passing},{total},{}", percent(*passing, *total))?;
+                    }
+                }
+            }
+        }
+        to.flush()
+    }
 }
 
 fn count_possible_queues(
@@ -98,34 +475,417 @@ fn count_possible_queues(
     queue_state: QueueState,
     revealed_pieces: usize
 )->usize{
-    //todo: optimize this by actually doing the math, atm its annoying with counted_bags setup
+    count_queues(counted_bags, queue_state, revealed_pieces, counted_bags.len())
+}
 
-    let mut count = 0;
+/// Every (board, hold) a player can be in after some pieces, or `None` once a
+/// perfect clear may already be done
+type Reachable = Option<Vec<(Board, Option<Shape>)>>;
 
-    let (bag_placement, bag) = &counted_bags[revealed_pieces];
-    let queue_state = if bag_placement == &0{queue_state.next(&bag)}else{queue_state};
+/// The node over the children which were reached, if any were
+fn collect_nodes(children: Vec<Option<(Shape, PrefixNode)>>) -> Option<PrefixNode> {
+    let mut node = PrefixNode::default();
+    for (shape, child) in children.into_iter().flatten(){
+        node.add(shape, child);
+    }
+    (!node.next.is_empty()).then_some(node)
+}
 
-    for shape in Shape::ALL{
-        if let Some(queue_state) = queue_state.take(&bag, shape){
-            count += count_possible_queues(counted_bags, queue_state, revealed_pieces+1)
+/// A state the first move leads to, as in [`max_limited_see_queues`], with
+/// every state the pieces revealed after it can reach
+#[derive(Clone)]
+struct Start {
+    board: Board,
+    hold: Option<Shape>,
+    just_held: bool,
+    reachable: Reachable,
+}
+
+impl Start {
+    fn new(board: Board, hold: Option<Shape>, just_held: bool, two_line: bool) -> Self {
+        let done = board == Board::full() || (two_line && board == Board::half());
+        Start{board, hold, just_held, reachable: if done {None} else {Some(vec![(board, hold)])}}
+    }
+}
+
+/// Walks the prefix tree of the pattern down to the revealed queues, so that
+/// work on a shared prefix is only done once.  Once the first move is seen,
+/// the states it leads to are carried down, dropping each one which can't
+/// place the pieces revealed after it, and the revealed queues are searched
+/// on from the ones left.  Prefixes which none survive are failed without
+/// searching their queues.
+struct PrefixWalk<'a> {
+    search: Search<'a>,
+    board: Board,
+    init_hold: bool,
+    /// How many pieces are seen before the first placement
+    revealed: usize,
+    bar: &'a indicatif::ProgressBar,
+    progress: &'a Progress,
+    checkpoint: Option<&'a Checkpoint>,
+    deadline: Option<Instant>,
+    /// Every full queue of the pattern
+    queue_count: usize,
+    /// Full queues which pass seeing the whole queue, by prefix
+    full_see: Option<PrefixNode>,
+}
+
+impl PrefixWalk<'_> {
+    /// Evaluate every revealed queue which starts with `prefix`, in order,
+    /// where `rank` is the rank of the first full queue starting with it and
+    /// `full` is the prefix in [`full_see`](Self::full_see).  Once the run is
+    /// cancelled or stopped, the queues left are skipped, and `None` means
+    /// none were evaluated.
+    fn walk(
+        &self,
+        feasibility: &mut Feasibility,
+        prefix: &mut VecDeque<Shape>,
+        rank: usize,
+        queue_state: QueueState,
+        starts: Option<Vec<Start>>,
+        full: Option<&PrefixNode>,
+    ) -> Option<PrefixNode> {
+        if self.stopping(){
+            return None;
+        }
+        let depth = prefix.len();
+        if depth < self.revealed && starts.as_ref().is_some_and(|starts| starts.is_empty()){
+            // every queue after the prefix fails, however it goes on
+            let count = count_possible_queues(self.search.counted_bags, queue_state, depth);
+            let revealed = count_queues(self.search.counted_bags, queue_state, depth, self.revealed) as u64;
+            self.progress.upper.fetch_sub(full.map_or(count, |full| full.passing), Ordering::Relaxed);
+            self.bar.inc(revealed);
+            self.progress.done.fetch_add(revealed as usize, Ordering::Relaxed);
+            return Some(PrefixNode{passing: 0, total: count, next: Vec::new()});
+        }
+        if depth >= self.revealed{
+            let result = match self.checkpoint.and_then(|checkpoint| checkpoint.get(rank)){
+                Some(result) => result,
+                None => {
+                    let result = self.evaluate(feasibility, prefix, queue_state, &starts.unwrap());
+                    if let Some(Err(error)) = self.checkpoint.map(|checkpoint| checkpoint.record(rank, result)){
+                        eprintln!("unable to write the checkpoint, giving up: {error}");
+                        self.progress.cancel();
+                    }
+                    result
+                }
+            };
+            let count = count_possible_queues(self.search.counted_bags, queue_state, self.revealed);
+            let could_pass = full.map_or(count, |full| full.passing);
+            // raise the lower bound first, so the bounds always hold.  Limited
+            // see can't pass more than full see, but a checkpoint from a bad
+            // file could claim to, and that mustn't wrap the upper bound.
+            self.progress.lower.fetch_add(result.0, Ordering::Relaxed);
+            self.progress.upper.fetch_sub(could_pass.saturating_sub(result.0), Ordering::Relaxed);
+            self.bar.inc(1);
+            self.progress.done.fetch_add(1, Ordering::Relaxed);
+            return Some(PrefixNode{passing: result.0, total: count, next: Vec::new()});
+        }
+
+        let (bag_placement, bag) = &self.search.counted_bags[depth];
+        let queue_state = if bag_placement == &0{queue_state.next(bag)}else{queue_state};
+
+        let first = 1 + self.init_hold as usize;
+        let mut rank = rank;
+        let children: Vec<_> = Shape::ALL.into_iter().filter_map(|shape|{
+            let queue_state = queue_state.take(bag, shape)?;
+            let starts = match &starts{
+                None if depth + 1 == first => Some(self.first_moves(prefix.front().copied().filter(|_| self.init_hold), shape)),
+                None => None,
+                // with nothing in hold and the same piece next, the first move holds
+                Some(starts) => Some(self.advance_starts(starts, shape, depth == first && self.search.use_hold && !self.init_hold && prefix.back() == Some(&shape))),
+            };
+            let child_rank = rank;
+            rank += count_possible_queues(self.search.counted_bags, queue_state, depth + 1);
+            Some((shape, child_rank, queue_state, starts))
+        }).collect();
+
+        let results: Vec<_> = children.into_par_iter().map_init(Feasibility::new, |feasibility, (shape, rank, queue_state, starts)|{
+            let mut prefix = prefix.clone();
+            prefix.push_back(shape);
+            let full = full.and_then(|full| full.next.iter().find(|(next, _)| next == &shape)).map(|(_, full)| full);
+            Some((shape, self.walk(feasibility, &mut prefix, rank, queue_state, starts, full)?))
+        }).collect();
+        collect_nodes(results)
+    }
+
+    /// Whether the run is cancelled, or should stop now by its [`Stop`]
+    fn stopping(&self) -> bool {
+        if self.progress.is_cancelled() || self.progress.is_stopped(){
+            return true;
         }
+        let out_of_time = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        let gap = self.progress.upper() - self.progress.lower().min(self.progress.upper());
+        let close_enough = self.progress.stop.gap.is_some_and(|percent| gap as f64 <= percent / 100.0 * self.queue_count as f64);
+        if out_of_time || close_enough{
+            self.progress.stopped.store(true, Ordering::Relaxed);
+        }
+        out_of_time || close_enough
+    }
+
+    /// Solve every full queue after the first `depth` pieces seeing the whole
+    /// queue, lowering the upper bound by the ones which fail, and count the
+    /// ones which pass by prefix.  Revealed queues left when the run stops
+    /// aren't counted.
+    fn full_see(
+        &self,
+        depth: usize,
+        queue_state: QueueState,
+        reachable: Reachable,
+    ) -> Option<PrefixNode> {
+        if self.stopping(){
+            return None;
+        }
+        if depth >= self.revealed || reachable.as_ref().is_some_and(|states| states.is_empty()){
+            let count = count_possible_queues(self.search.counted_bags, queue_state, depth);
+            let passing = self.full_see_passing(depth, queue_state, reachable);
+            self.progress.upper.fetch_sub(count.saturating_sub(passing), Ordering::Relaxed);
+            return Some(PrefixNode{passing, total: count, next: Vec::new()});
+        }
+
+        let (bag_placement, bag) = &self.search.counted_bags[depth];
+        let queue_state = if bag_placement == &0{queue_state.next(bag)}else{queue_state};
+        let children: Vec<_> = Shape::ALL.into_iter().filter_map(|shape|{
+            let queue_state = queue_state.take(bag, shape)?;
+            let reachable = reachable.as_ref().and_then(|states| self.advance(states, shape, depth == 0));
+            Some((shape, queue_state, reachable))
+        }).collect();
+
+        let results: Vec<_> = children.into_par_iter().map(|(shape, queue_state, reachable)|{
+            Some((shape, self.full_see(depth + 1, queue_state, reachable)?))
+        }).collect();
+        collect_nodes(results)
+    }
+
+    /// Full queues after the first `depth` pieces which pass seeing the whole
+    /// queue, from the states those pieces can reach
+    fn full_see_passing(&self, depth: usize, queue_state: QueueState, reachable: Reachable) -> usize {
+        let states = match reachable{
+            Some(states) => states,
+            None => return count_possible_queues(self.search.counted_bags, queue_state, depth),
+        };
+        if states.is_empty() || depth >= self.search.counted_bags.len(){
+            return 0;
+        }
+
+        let (bag_placement, bag) = &self.search.counted_bags[depth];
+        let queue_state = if bag_placement == &0{queue_state.next(bag)}else{queue_state};
+        Shape::ALL.into_iter().filter_map(|shape|{
+            let queue_state = queue_state.take(bag, shape)?;
+            Some(self.full_see_passing(depth + 1, queue_state, self.advance(&states, shape, depth == 0)))
+        }).sum()
+    }
+
+    /// Passing hidden queues after a revealed queue, going on from the first
+    /// moves which survived it
+    fn evaluate(
+        &self,
+        feasibility: &mut Feasibility,
+        queue: &mut VecDeque<Shape>,
+        queue_state: QueueState,
+        starts: &[Start],
+    ) -> (usize, Option<usize>) {
+        let count = count_possible_queues(self.search.counted_bags, queue_state, self.revealed);
+        if starts.is_empty(){
+            return (0, Some(count))
+        }
+
+        if self.revealed >= self.search.counted_bags.len(){
+            // nothing is left to reveal, so the whole queue is searched as one
+            let hold = if self.init_hold{
+                Some(queue.pop_front().unwrap())
+            }else{
+                None
+            };
+            let result = max_limited_see_queues(&self.search, feasibility, self.board, hold, false, queue_state, queue, self.revealed);
+            if let Some(hold) = hold{queue.push_front(hold);}
+            return result
+        }
+
+        let (bag_placement, bag) = &self.search.counted_bags[self.revealed];
+        let queue_state = if bag_placement == &0{queue_state.next(bag)}else{queue_state};
+        let mut seen: VecDeque<Shape> = queue.iter().skip(1 + self.init_hold as usize).copied().collect();
+        let mut max = 0;
+        for start in starts{
+            let mut passing = 0;
+            for shape in Shape::ALL{
+                let Some(queue_state) = queue_state.take(bag, shape) else {continue};
+                seen.push_back(shape);
+                passing += max_limited_see_queues(&self.search, feasibility, start.board, start.hold, start.just_held, queue_state, &mut seen, self.revealed+1).0;
+                seen.pop_back();
+            }
+            max = max.max(passing);
+            if max == count{break;}
+        }
+        (max, Some(count))
+    }
+
+    /// Every state the first move, of `current` with `hold` in hold, leads to
+    fn first_moves(&self, hold: Option<Shape>, current: Shape) -> Vec<Start> {
+        let edges = match self.search.gigapan.edges(self.board){
+            Some(edges) => edges,
+            None => return Vec::new(),
+        };
+        let mut starts = Vec::new();
+        let mut place = |placed: Shape, hold: Option<Shape>|{
+            for new_board in edges.get(placed){
+                if let Some(culled) = self.search.culled{if !culled.contains(&new_board){continue;}}
+                starts.push(Start::new(new_board, hold, false, self.search.two_line));
+            }
+        };
+        place(current, hold);
+        if let Some(held) = hold.filter(|&held| self.search.use_hold && held != current){
+            place(held, Some(current));
+        }
+        if self.search.use_hold && hold.is_none(){
+            starts.push(Start::new(self.board, Some(current), true, self.search.two_line));
+        }
+        starts
+    }
+
+    /// The starts which can still place the pieces after `shape` comes out of
+    /// the queue, or only the ones which held if `held_only`
+    fn advance_starts(&self, starts: &[Start], shape: Shape, held_only: bool) -> Vec<Start> {
+        starts.iter().filter(|start| start.just_held || !held_only).filter_map(|start|{
+            let reachable = match &start.reachable{
+                Some(states) => self.advance(states, shape, false),
+                None => None,
+            };
+            if reachable.as_ref().is_some_and(|states| states.is_empty()){
+                return None
+            }
+            Some(Start{reachable, ..*start})
+        }).collect()
+    }
+
+    /// The states after `shape` comes out of the queue
+    fn advance(&self, states: &[(Board, Option<Shape>)], shape: Shape, first: bool) -> Reachable {
+        if first && self.init_hold{
+            return Some(states.iter().map(|&(board, _)| (board, Some(shape))).collect())
+        }
+
+        let mut next = HashSet::new();
+        for &(board, hold) in states{
+            let edges = match self.search.gigapan.edges(board){
+                Some(edges) => edges,
+                None => continue,
+            };
+            let mut place = |placed: Shape, hold: Option<Shape>|{
+                for new_board in edges.get(placed){
+                    if let Some(culled) = self.search.culled{if !culled.contains(&new_board){continue;}}
+                    next.insert((new_board, hold));
+                }
+            };
+            place(shape, hold);
+            if self.search.use_hold{
+                match hold{
+                    None => {next.insert((board, Some(shape)));},
+                    Some(hold) if hold != shape => place(hold, Some(shape)),
+                    Some(_) => {},
+                }
+            }
+        }
+
+        if next.iter().any(|&(board, _)| board == Board::full() || (self.search.two_line && board == Board::half())){
+            return None
+        }
+        let mut next: Vec<_> = next.into_iter().collect();
+        next.sort_unstable();
+        Some(next)
+    }
+}
+
+/// Results of [`max_limited_see_queues`] shared between every queue of a run,
+/// since many queues reach the same states.  Each shard is emptied once it's
+/// full, so a long run or a sweep keeps a bounded number of results.
+pub struct LimitedSeeMemo(ShardedHashMap<LimitedSeeKey, (usize, Option<usize>), 8>);
+
+impl LimitedSeeMemo {
+    /// Results kept by each of the 256 shards, about 4 million in all
+    const SHARD_CAPACITY: usize = 1 << 14;
+
+    pub fn new() -> Self {
+        LimitedSeeMemo(ShardedHashMap::new())
+    }
+
+    fn get(&self, key: &LimitedSeeKey) -> Option<(usize, Option<usize>)> {
+        self.0.get_shard_guard(key).get(key).copied()
+    }
+
+    fn insert(&self, key: LimitedSeeKey, result: (usize, Option<usize>)) {
+        let mut shard = self.0.get_shard_guard(&key);
+        if shard.len() >= Self::SHARD_CAPACITY {
+            shard.clear();
+        }
+        shard.insert(key, result);
+    }
+}
+
+impl Default for LimitedSeeMemo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Everything a limited see result depends on, apart from the settings which
+/// are fixed for the whole run
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LimitedSeeKey {
+    board: Board,
+    hold: Option<Shape>,
+    just_held: bool,
+    queue_state: QueueState,
+    revealed_pieces: u8,
+    /// The visible queue, 3 bits per shape, after a leading 1 bit
+    queue: u64,
+}
+
+impl LimitedSeeKey {
+    fn new(
+        board: Board,
+        hold: Option<Shape>,
+        just_held: bool,
+        queue_state: QueueState,
+        queue: &VecDeque<Shape>,
+        revealed_pieces: usize,
+    ) -> Option<Self> {
+        if queue.len() > 20 {
+            return None;
+        }
+        let queue = queue.iter().fold(1, |packed, &shape| packed << 3 | shape as u64);
+        Some(LimitedSeeKey {
+            board,
+            hold,
+            just_held,
+            queue_state,
+            revealed_pieces: revealed_pieces.try_into().ok()?,
+            queue,
+        })
     }
-    return count
+}
+
+/// What every limited see search over one pattern shares
+#[derive(Clone, Copy)]
+pub struct Search<'a> {
+    pub gigapan: &'a FrozenGigapan,
+    pub culled: Option<&'a HashSet<Board>>,
+    pub memo: &'a LimitedSeeMemo,
+    pub counted_bags: &'a [(u8, Bag)],
+    pub use_hold: bool,
+    pub two_line: bool,
 }
 
 ///DFS search to find the maximum found hidden queues that conform to limited see, and the maximum possible hidden queues
 pub fn max_limited_see_queues(
-    gigapan: &FrozenGigapan,
-    culled: Option<&HashSet<Board>>,
+    search: &Search,
+    feasibility: &mut Feasibility,
     board: Board,
     hold: Option<Shape>,
-    use_hold: bool,
     just_held: bool,
-    two_line: bool,
-    counted_bags: &[(u8, Bag)],
     queue_state: QueueState,
     queue: &mut VecDeque<Shape>,
     revealed_pieces: usize)-> (usize, Option<usize>){
+    let &Search { gigapan, culled, memo, counted_bags, use_hold, two_line } = search;
 
     if two_line && board == Board::half() || board==Board::full(){ // will only happen on low see i think, just in case
         let total = count_possible_queues(counted_bags, queue_state, revealed_pieces);
@@ -136,25 +896,45 @@ pub fn max_limited_see_queues(
         let res =  match hold{
             Some(hold) => {
                 if use_hold{
-                    test_set_queue_with_hold(gigapan, culled, board, queue, hold, two_line)
+                    test_set_queue_with_hold(gigapan, culled, board, queue, hold, two_line, None)
                 }else{
-                    test_set_queue_without_hold(gigapan, culled, board, queue, two_line)
+                    test_set_queue_without_hold(gigapan, culled, board, queue, two_line, None)
                 }
             },
             None => {
                 if use_hold{
                     let new_hold = queue.pop_front().unwrap();
-                    let res = test_set_queue_with_hold(gigapan, culled, board, queue, new_hold, two_line);
+                    let res = test_set_queue_with_hold(gigapan, culled, board, queue, new_hold, two_line, None);
                     queue.push_front(new_hold);
                     res
                 }else{
-                    test_set_queue_without_hold(gigapan, culled, board, queue, two_line)
+                    test_set_queue_without_hold(gigapan, culled, board, queue, two_line, None)
                 }
             },
         };
         return (res as usize, Some(1))
     }
 
+    let key = LimitedSeeKey::new(board, hold, just_held, queue_state, queue, revealed_pieces);
+    if let Some(key) = &key{
+        if let Some(result) = memo.get(key){
+            return result
+        }
+    }
+
+    // no order of the pieces still to come can fill the board, so every hidden queue fails
+    if !two_line{
+        let mut available = unrevealed_shapes(counted_bags, queue_state, revealed_pieces);
+        for &shape in queue.iter().chain(hold.iter()){
+            available.add(shape, 1);
+        }
+        if !feasibility.is_feasible(board, available){
+            let result = (0, Some(count_possible_queues(counted_bags, queue_state, revealed_pieces)));
+            if let Some(key) = key{memo.insert(key, result);}
+            return result
+        }
+    }
+
 
     let (bag_placement, bag) = &counted_bags[revealed_pieces];
 
@@ -164,7 +944,7 @@ pub fn max_limited_see_queues(
     let use_shape = queue.pop_front().unwrap();
     let mut max = 0;
 
-    let edges = gigapan.get(&board).expect(format!("board not found in giga: {}", board).as_str());
+    let edges = gigapan.edges(board).expect(format!("board not found in giga: {}", board).as_str());
     let next_states: Vec<_> = Shape::ALL.iter().filter_map(|&shape|{
         if let Some(queue_state) = queue_state.take(&bag, shape){
             Some((shape, queue_state))
@@ -180,9 +960,7 @@ pub fn max_limited_see_queues(
     let mut max_found = false;
  
     if use_hold && hold.is_none() && !just_held{
-        let next_shape = queue.pop_front().unwrap();
-
-        if next_shape == use_shape{
+        if queue.front() == Some(&use_shape){
             oqb_skip = true; //if current and next are equal, and theres no piece in hold, the best choice is always to hold to reveal extra piece
         }
 
@@ -191,7 +969,7 @@ pub fn max_limited_see_queues(
 
         for (idx, &(shape, queue_state)) in next_states.iter().enumerate(){
             queue.push_back(shape);
-            let (next_count, next_possible_queues) = max_limited_see_queues(gigapan, culled, board, Some(use_shape), use_hold, true, two_line, counted_bags, queue_state, queue, revealed_pieces+1);
+            let (next_count, next_possible_queues) = max_limited_see_queues(search, feasibility, board, Some(use_shape), true, queue_state, queue, revealed_pieces+1);
             count += next_count;
             if let Some(next_possible_queues) = next_possible_queues{
                 if next_count == next_possible_queues{max_count+=1;}
@@ -206,7 +984,7 @@ pub fn max_limited_see_queues(
     }
 
     if !oqb_skip && !max_found{
-        for &new_board in &edges[use_shape as usize] {
+        for new_board in edges.get(use_shape) {
             if let Some(culled) = culled{if !culled.contains(&new_board){continue;}}
             let mut count = 0;
             let mut max_count = 0;
@@ -214,7 +992,7 @@ pub fn max_limited_see_queues(
             for (idx, &(shape, queue_state)) in next_states.iter().enumerate(){
                 queue.push_back(shape);
 
-                let (next_count, next_possible_queues) = max_limited_see_queues(gigapan, culled, new_board, hold, use_hold, false, two_line, counted_bags, queue_state, queue, revealed_pieces+1);
+                let (next_count, next_possible_queues) = max_limited_see_queues(search, feasibility, new_board, hold, false, queue_state, queue, revealed_pieces+1);
                 count += next_count;
                 if let Some(next_possible_queues) = next_possible_queues{
                     if next_count == next_possible_queues{max_count+=1;}
@@ -231,14 +1009,14 @@ pub fn max_limited_see_queues(
     if use_hold && hold.is_some() && !max_found && !just_held{
         let hold = hold.unwrap();
         if use_shape != hold{
-            for &new_board in &edges[hold as usize] {
+            for new_board in edges.get(hold) {
                 if let Some(culled) = culled{if !culled.contains(&new_board){continue;}}
                 let mut count = 0;
                 let mut max_count = 0;
         
                 for (idx, &(shape, queue_state)) in next_states.iter().enumerate(){
                     queue.push_back(shape);
-                    let (next_count, next_possible_queues) = max_limited_see_queues(gigapan, culled, new_board, Some(use_shape), use_hold, false, two_line, counted_bags, queue_state, queue, revealed_pieces+1);
+                    let (next_count, next_possible_queues) = max_limited_see_queues(search, feasibility, new_board, Some(use_shape), false, queue_state, queue, revealed_pieces+1);
                     count += next_count;
                     if let Some(next_possible_queues) = next_possible_queues{
                         if next_count == next_possible_queues{max_count+=1;}
@@ -256,46 +1034,51 @@ pub fn max_limited_see_queues(
 
     queue.push_front(use_shape);
 
-    let mut next_cutoff = 0;
-    for cutoff in cutoffs{
-        match cutoff{
-            Some(cutoff)=>{next_cutoff+=cutoff},
-            None=> return (max, None)
-        }
-    }
+    let next_cutoff = cutoffs.into_iter().sum::<Option<usize>>();
 
-    (max, Some(next_cutoff))
+    if let Some(key) = key{memo.insert(key, (max, next_cutoff));}
+    (max, next_cutoff)
 }
 
 ///DFS search to see if the given (board,queue,hold) state achieved PC
-fn test_set_queue_with_hold(
+/// Whether the set queue reaches a perfect clear, holding whenever it helps.
+/// With `steps`, the placements of the first way found are left in it.
+pub(crate) fn test_set_queue_with_hold(
     gigapan: &FrozenGigapan,
     culled: Option<&HashSet<Board>>,
     start_board: Board,
     start_queue: &mut VecDeque<Shape>,
     start_hold: Shape,
-    two_line: bool
+    two_line: bool,
+    mut steps: Option<&mut Vec<Step>>
 )->bool{
     if start_board == Board::full() || (two_line && start_board==Board::half()){
         return true;
     }
-    let use_shape = start_queue.pop_front().expect(format!("no queue... {} {:?}", start_board, start_hold).as_str());
+    let use_shape = match start_queue.pop_front(){
+        Some(shape) => shape,
+        None => return false,
+    };
     let mut result = false;
 
-    let edges = gigapan.get(&start_board).unwrap();
-    for &new_board in &edges[use_shape as usize] {
+    let edges = gigapan.edges(start_board).unwrap();
+    for new_board in edges.get(use_shape) {
         if let Some(culled) = culled{if !culled.contains(&new_board){continue;}}
-        if test_set_queue_with_hold(gigapan, culled, new_board, start_queue, start_hold, two_line){
+        if let Some(steps) = steps.as_deref_mut(){steps.push(Step { shape: use_shape, board: new_board, held: false });}
+        if test_set_queue_with_hold(gigapan, culled, new_board, start_queue, start_hold, two_line, steps.as_deref_mut()){
             result = true;break;
         }
+        if let Some(steps) = steps.as_deref_mut(){steps.pop();}
     }
 
-    if start_hold != use_shape{
-        for &new_board in &edges[start_hold as usize] {
+    if !result && start_hold != use_shape{
+        for new_board in edges.get(start_hold) {
             if let Some(culled) = culled{if !culled.contains(&new_board){continue;}}
-            if test_set_queue_with_hold(gigapan, culled, new_board, start_queue, use_shape, two_line){
+            if let Some(steps) = steps.as_deref_mut(){steps.push(Step { shape: start_hold, board: new_board, held: true });}
+            if test_set_queue_with_hold(gigapan, culled, new_board, start_queue, use_shape, two_line, steps.as_deref_mut()){
                 result = true;break;
             }
+            if let Some(steps) = steps.as_deref_mut(){steps.pop();}
         }
     }
 
@@ -303,25 +1086,33 @@ fn test_set_queue_with_hold(
     return result;
 }
 
-fn test_set_queue_without_hold(
+/// Whether the set queue reaches a perfect clear placed in order.  With
+/// `steps`, the placements of the first way found are left in it.
+pub(crate) fn test_set_queue_without_hold(
     gigapan: &FrozenGigapan,
     culled: Option<&HashSet<Board>>,
     start_board: Board,
     start_queue: &mut VecDeque<Shape>,
-    two_line: bool
+    two_line: bool,
+    mut steps: Option<&mut Vec<Step>>
 )->bool{
     if start_board == Board::full() || (two_line && start_board==Board::half()){
         return true;
     }
-    let use_shape = start_queue.pop_front().unwrap();
+    let use_shape = match start_queue.pop_front(){
+        Some(shape) => shape,
+        None => return false,
+    };
     let mut result = false;
 
-    let edges = gigapan.get(&start_board).unwrap();
-    for &new_board in &edges[use_shape as usize] {
+    let edges = gigapan.edges(start_board).unwrap();
+    for new_board in edges.get(use_shape) {
         if let Some(culled) = culled{if !culled.contains(&new_board){continue;}}
-        if test_set_queue_without_hold(gigapan, culled, new_board, start_queue, two_line){
+        if let Some(steps) = steps.as_deref_mut(){steps.push(Step { shape: use_shape, board: new_board, held: false });}
+        if test_set_queue_without_hold(gigapan, culled, new_board, start_queue, two_line, steps.as_deref_mut()){
             result = true;break;
         }
+        if let Some(steps) = steps.as_deref_mut(){steps.pop();}
     }
 
     start_queue.push_front(use_shape);
@@ -342,15 +1133,17 @@ fn get_culled_boards(gigapan: &FrozenGigapan, start: Board, counted_bags: &[(u8,
         let next: ScanStage = ShardedHashMap::new();
 
         prev.par_iter_mut().for_each(|(&old_board, (old_queues, _))|{
-            for (shape, new_boards) in gigapan.get(&old_board).unwrap().into_iter().enumerate() {
-                let shape = Shape::try_from(shape as u8).unwrap();
+            if old_board.is_dead() {
+                return;
+            }
+            for (shape, new_boards) in gigapan.edges(old_board).unwrap().iter() {
                 let new_queues = bag.take(old_queues, shape, i == &0, use_hold);
 
                 if new_queues.is_empty() {
                     continue;
                 }
 
-                for &new_board in new_boards {
+                for new_board in new_boards {
                     let mut lock = next.get_shard_guard(&new_board);
                     let (queues, preds) = lock.entry(new_board).or_default();
                     for &queue in &new_queues {
@@ -394,3 +1187,220 @@ fn get_culled_boards(gigapan: &FrozenGigapan, start: Board, counted_bags: &[(u8,
     }
     culled
 }
+
+#[test]
+fn percent_tree(){
+    use Shape::*;
+    let leaf = |passing, total| PrefixNode{passing, total, next: Vec::new()};
+    let mut prefixes = PrefixNode::default();
+    let mut i = PrefixNode::default();
+    i.add(O, leaf(2, 4));
+    let mut t = PrefixNode::default();
+    t.add(I, leaf(2, 2));
+    t.add(O, leaf(1, 2));
+    prefixes.add(I, i);
+    prefixes.add(T, t);
+    // failing as a whole, so it isn't extended
+    prefixes.add(Z, leaf(0, 4));
+    let result = ChanceResult{
+        board: Board::empty(),
+        pattern: String::new(),
+        previews: 1,
+        init_hold: false,
+        use_hold: false,
+        culled: false,
+        two_line: false,
+        passing: 5,
+        upper: 5,
+        complete: true,
+        total: 12,
+        prefixes,
+        elapsed: Duration::ZERO,
+    };
+    assert_eq!(result.fraction(), (5, 12));
+    assert_eq!(result.tree(1), vec![("I".to_string(), 2, 4), ("T".to_string(), 3, 4), ("Z".to_string(), 0, 4)]);
+    assert_eq!(result.tree(2)[..3], [("I".to_string(), 2, 4), ("IO".to_string(), 2, 4), ("T".to_string(), 3, 4)]);
+    assert_eq!(result.tree(2).last(), Some(&("Z".to_string(), 0, 4)));
+    assert_eq!(result.queues(), vec![
+        (VecDeque::from([I, O]), 2, 4),
+        (VecDeque::from([T, I]), 2, 2),
+        (VecDeque::from([T, O]), 1, 2),
+        (VecDeque::from([Z]), 0, 4),
+    ]);
+}
+
+#[test]
+fn prefix_walk(){
+    use std::str::FromStr;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let queue = CombinatoricQueue::from_str("O,*p6").unwrap();
+    let counted_bags = queue.get_counted_bags();
+
+    // Each queue, or prefix failing as a whole, must get what searching the
+    // revealed queues starting with it on their own gets, apart from the
+    // maximum of queues which were cut off.  Without hold, placing the pieces
+    // seen fails some prefixes as a whole.
+    for (previews, use_hold, revealed_count) in [(1, true, 42), (2, false, 210)]{
+        let result = limited_see_chance(&gigapan, board, &queue, previews, true, use_hold, false, false).unwrap();
+        assert_eq!(result.passing > 0, use_hold);
+        assert_eq!(result.queues().len() < revealed_count, !use_hold);
+
+        let revealed = crate::queue::get_queue_permutations(&counted_bags, None, Some(previews + 2));
+        assert_eq!(revealed.len(), revealed_count);
+        let memo = LimitedSeeMemo::new();
+        let search = Search{gigapan: &gigapan, culled: None, memo: &memo, counted_bags: &counted_bags, use_hold, two_line: false};
+        let mut feasibility = Feasibility::new();
+        let mut matched = 0;
+        for (prefix, covered, maximum) in result.queues(){
+            let (mut expected, mut expected_maximum) = (0, Some(0));
+            for queue in revealed.iter().filter(|queue| queue.iter().take(prefix.len()).eq(prefix.iter())){
+                let mut queue_state = QueueState(counted_bags[0].1.full);
+                for ((i, bag), shape) in counted_bags.iter().zip(queue.iter()){
+                    let state = if i == &0 { queue_state.next(bag) } else { queue_state };
+                    queue_state = state.take(bag, *shape).unwrap();
+                }
+                let mut rest = queue.clone();
+                let hold = rest.pop_front();
+                let (passing, possible) = max_limited_see_queues(&search, &mut feasibility, board, hold, false, queue_state, &mut rest, previews + 2);
+                expected += passing;
+                expected_maximum = expected_maximum.zip(possible).map(|(a, b)| a + b);
+                matched += 1;
+            }
+            assert_eq!(covered, expected, "{prefix:?}");
+            if covered > 0{
+                assert_eq!(Some(maximum), expected_maximum);
+            }
+        }
+        assert_eq!(matched, revealed.len());
+    }
+}
+
+#[test]
+fn blank_start(){
+    use std::str::FromStr;
+
+    // Passing full queues from a position seeing `seen`, trying every move and
+    // every piece revealed after it, where `position` pieces are revealed
+    fn brute_force(gigapan: &FrozenGigapan, counted_bags: &[(u8, Bag)], board: Board, hold: Option<Shape>, seen: &[Shape], position: usize, queue_state: QueueState) -> usize{
+        if board == Board::full(){
+            return count_queues(counted_bags, queue_state, position, counted_bags.len());
+        }
+        let (edges, (&current, rest)) = match (gigapan.edges(board), seen.split_first()){
+            (Some(edges), Some(split)) => (edges, split),
+            _ => return 0,
+        };
+        let after = |board: Board, hold: Option<Shape>|{
+            if position >= counted_bags.len(){
+                return brute_force(gigapan, counted_bags, board, hold, rest, position, queue_state);
+            }
+            let (bag_placement, bag) = &counted_bags[position];
+            let state = if bag_placement == &0{queue_state.next(bag)}else{queue_state};
+            Shape::ALL.into_iter().filter_map(|shape|{
+                let state = state.take(bag, shape)?;
+                let seen: Vec<Shape> = rest.iter().copied().chain([shape]).collect();
+                Some(brute_force(gigapan, counted_bags, board, hold, &seen, position+1, state))
+            }).sum()
+        };
+        let placed = edges.get(current).map(|child| after(child, hold));
+        let held: Box<dyn Iterator<Item = usize>> = match hold{
+            None => Box::new(std::iter::once(after(board, Some(current)))),
+            Some(held) => Box::new(edges.get(held).map(|child| after(child, Some(current)))),
+        };
+        placed.chain(held).max().unwrap_or(0)
+    }
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let queue = CombinatoricQueue::from_str("*p7").unwrap();
+    let counted_bags = queue.get_counted_bags();
+
+    for previews in [1, 2]{
+        let revealed = previews + 1;
+        let expected: usize = crate::queue::get_queue_permutations(&counted_bags, None, Some(revealed)).into_iter().map(|seen|{
+            let mut queue_state = QueueState(counted_bags[0].1.full);
+            for ((bag_placement, bag), shape) in counted_bags.iter().zip(seen.iter()){
+                let state = if bag_placement == &0 { queue_state.next(bag) } else { queue_state };
+                queue_state = state.take(bag, *shape).unwrap();
+            }
+            brute_force(&gigapan, &counted_bags, board, None, &Vec::from(seen), revealed, queue_state)
+        }).sum();
+
+        let result = limited_see_chance(&gigapan, board, &queue, previews, false, true, false, false).unwrap();
+        assert_eq!(result.passing, expected, "{previews} previews");
+    }
+}
+
+#[test]
+fn bounds() {
+    use std::str::FromStr;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let queue = CombinatoricQueue::from_str("O,*p6").unwrap();
+    let exact = limited_see_chance(&gigapan, board, &queue, 1, true, true, false, false).unwrap();
+
+    // with nothing stopping it, the bounds close on the chance
+    let progress = Progress::with_stop(Stop {
+        time: Some(Duration::from_secs(3600)),
+        gap: None,
+    });
+    let options = ChanceOptions {
+        previews: 1,
+        init_hold: true,
+        use_hold: true,
+        progress: Some(&progress),
+        ..ChanceOptions::default()
+    };
+    let result = limited_see_chance_watched(&gigapan, board, &queue, &options).unwrap();
+    assert!(result.complete && !progress.is_stopped());
+    assert_eq!(
+        (result.passing, result.upper),
+        (exact.passing, exact.passing)
+    );
+    assert_eq!(
+        (progress.lower(), progress.upper()),
+        (exact.passing, exact.passing)
+    );
+
+    for gap in [100.0, 10.0] {
+        let progress = Progress::with_stop(Stop {
+            time: None,
+            gap: Some(gap),
+        });
+        let options = ChanceOptions {
+            previews: 1,
+            init_hold: true,
+            use_hold: true,
+            progress: Some(&progress),
+            ..ChanceOptions::default()
+        };
+        let result = limited_see_chance_watched(&gigapan, board, &queue, &options).unwrap();
+        assert!(result.passing <= exact.passing && exact.passing <= result.upper);
+        assert!(
+            result.complete
+                || (result.upper - result.passing) as f64 <= gap / 100.0 * result.total as f64
+        );
+    }
+}
+
+#[test]
+fn tree_totals(){
+    use std::str::FromStr;
+
+    let (board, gigapan) = crate::fixture::small_board();
+
+    // without hold and previews, the search gives up on some queues before
+    // finding how many there are
+    let mut failing = 0;
+    for (pattern, previews, use_hold) in [("O,*p6", 0, false), ("O,*p6", 1, true), ("*p7", 1, true)]{
+        let queue = CombinatoricQueue::from_str(pattern).unwrap();
+        let queues = crate::queue::get_queue_permutations(&queue.get_counted_bags(), None, None);
+        let result = limited_see_chance(&gigapan, board, &queue, previews, true, use_hold, false, false).unwrap();
+        for (prefix, passing, total) in result.tree(2){
+            let starting = queues.iter().filter(|queue| queue.iter().map(|shape| shape.name()).collect::<String>().starts_with(&prefix)).count();
+            assert_eq!(total, starting, "{pattern} {previews} {prefix}");
+            assert!(passing <= total);
+            failing += (passing == 0) as usize;
+        }
+    }
+    assert!(failing > 0);
+}
@@ -0,0 +1,128 @@
+//! A results file for long chance runs, written as each revealed queue is
+//! done, so a run which is stopped or crashes can carry on where it was.
+//!
+//! The first line describes the run.  Each line after it is a revealed queue,
+//! as the [`prefix_rank`](crate::queue::CombinatoricQueue::prefix_rank) of its
+//! first full queue, how many of its hidden queues pass, and how many there
+//! are, or `-` if that wasn't found.  Revealed queues under a prefix which
+//! fails as a whole aren't written, since failing it again is cheap.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+pub struct Checkpoint {
+    done: HashMap<usize, (usize, Option<usize>)>,
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// Open the results file of the run described by `run`, reading what's
+    /// already done, or start it if it doesn't exist
+    pub fn open(path: &str, run: &str) -> std::io::Result<Self> {
+        let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        let header = format!("# {run}");
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mut lines = contents.lines();
+        match lines.next() {
+            None => writeln!(file, "{header}")?,
+            Some(line) if line == header => {}
+            Some(line) => return Err(invalid(format!("{path} is from another run: {line}"))),
+        }
+        // a line cut off by a crash is done again
+        let done = lines.filter_map(parse_line).collect();
+        // and the next line starts after it
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            writeln!(file)?;
+        }
+
+        Ok(Checkpoint { done, file: Mutex::new(file) })
+    }
+
+    /// Revealed queues already done
+    pub fn len(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.done.is_empty()
+    }
+
+    /// The result of the revealed queue with this rank, if it's done
+    pub fn get(&self, rank: usize) -> Option<(usize, Option<usize>)> {
+        self.done.get(&rank).copied()
+    }
+
+    /// Write down the result of the revealed queue with this rank
+    pub fn record(&self, rank: usize, (covered, maximum): (usize, Option<usize>)) -> std::io::Result<()> {
+        let maximum = maximum.map_or("-".to_string(), |maximum| maximum.to_string());
+        // one write per line, so a crash can only cut off the last one
+        let line = format!("{rank} {covered} {maximum}\n");
+        self.file.lock().unwrap().write_all(line.as_bytes())
+    }
+}
+
+fn parse_line(line: &str) -> Option<(usize, (usize, Option<usize>))> {
+    let mut fields = line.split(' ');
+    let rank = fields.next()?.parse().ok()?;
+    let covered = fields.next()?.parse().ok()?;
+    let maximum = match fields.next()? {
+        "-" => None,
+        maximum => Some(maximum.parse().ok()?),
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some((rank, (covered, maximum)))
+}
+
+#[test]
+fn resumes() {
+    use std::str::FromStr;
+
+    use crate::calculate::{limited_see_chance, limited_see_chance_watched, ChanceOptions};
+    use crate::queue::CombinatoricQueue;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let queue = CombinatoricQueue::from_str("O,*p6").unwrap();
+    let exact = limited_see_chance(&gigapan, board, &queue, 1, true, true, false, false).unwrap();
+
+    let path = std::env::temp_dir().join(format!("gigapan-checkpoint-{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let run = |run: &str| {
+        let checkpoint = Checkpoint::open(path, run).unwrap();
+        let options = ChanceOptions { previews: 1, init_hold: true, use_hold: true, checkpoint: Some(&checkpoint), ..ChanceOptions::default() };
+        let result = limited_see_chance_watched(&gigapan, board, &queue, &options);
+        (checkpoint.len(), result.unwrap())
+    };
+
+    let (resumed, result) = run("test");
+    assert_eq!((resumed, result.passing), (0, exact.passing));
+    assert!(Checkpoint::open(path, "another").is_err());
+
+    // cut the file off part way through a line, with a passing queue made
+    // to fail before it
+    let contents = std::fs::read_to_string(path).unwrap();
+    let mut lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 1 + 42);
+    let passes = lines.iter().position(|line| parse_line(line).is_some_and(|(_, (covered, _))| covered > 0)).unwrap();
+    lines.swap(1, passes);
+    let (rank, (covered, maximum)) = parse_line(lines[1]).unwrap();
+    let made_up = format!("{rank} 0 {}", maximum.unwrap());
+    lines[1] = &made_up;
+    let mut cut = lines[..20].join("\n");
+    cut.push_str("\n3");
+    std::fs::write(path, cut).unwrap();
+
+    let (resumed, result) = run("test");
+    assert_eq!(resumed, 19);
+    assert_eq!(result.passing, exact.passing - covered);
+    assert_eq!(Checkpoint::open(path, "test").unwrap().len(), 42);
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,145 @@
+//! Which queues of a pattern can build which solutions, like sfinder's cover
+//! command.
+
+use std::collections::VecDeque;
+use std::io::Write;
+
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use srs_4l::brokenboard::BrokenBoard;
+use srs_4l::gameplay::Shape;
+
+use crate::calculate::OutputFormat;
+use crate::fumens::encode_broken_boards;
+use crate::path::Cover;
+use crate::queue::{get_queue_permutations, CombinatoricQueue};
+
+/// For every full queue of a pattern, whether it can build each solution
+pub struct CoverMatrix {
+    pub solutions: Vec<BrokenBoard>,
+    pub queues: Vec<VecDeque<Shape>>,
+    /// Indexed by queue, then by solution
+    pub covered: Vec<Vec<bool>>,
+}
+
+/// The solution with its pieces taken out, leaving the starting garbage
+pub fn start_of(solution: &BrokenBoard) -> BrokenBoard {
+    let mut garbage = solution.to_broken_bitboard().0;
+    for piece in &solution.pieces {
+        garbage ^= piece.board().0;
+    }
+    BrokenBoard::from_garbage(garbage)
+}
+
+impl CoverMatrix {
+    pub fn compute(solutions: Vec<BrokenBoard>, combinatoric_queue: &CombinatoricQueue, use_hold: bool) -> Self {
+        let queues = get_queue_permutations(&combinatoric_queue.get_counted_bags(), None, None);
+
+        let by_solution: Vec<Vec<bool>> = solutions
+            .par_iter()
+            .map(|solution| {
+                let start = start_of(solution);
+                let mut cover = Cover::new(&start, solution, use_hold);
+                queues.iter().map(|queue| cover.covers(queue.iter().copied())).collect()
+            })
+            .collect();
+
+        let covered = (0..queues.len())
+            .map(|queue| by_solution.iter().map(|solution| solution[queue]).collect())
+            .collect();
+
+        CoverMatrix { solutions, queues, covered }
+    }
+
+    /// How many queues can build each solution
+    pub fn solution_counts(&self) -> Vec<usize> {
+        (0..self.solutions.len())
+            .map(|solution| self.covered.iter().filter(|row| row[solution]).count())
+            .collect()
+    }
+
+    /// How many queues can build at least one of the solutions
+    pub fn any_count(&self) -> usize {
+        self.covered.iter().filter(|row| row.iter().any(|&covered| covered)).count()
+    }
+
+    /// Write how many queues build each solution and any of them, then the
+    /// matrix with one row per queue and one column per solution
+    pub fn write(&self, format: OutputFormat, mut to: impl Write) -> std::io::Result<()> {
+        let fumens: Vec<String> = self.solutions.iter().map(|solution| encode_broken_boards([(solution, None)])).collect();
+        let queues = self.queues.iter().map(|queue| queue.iter().map(|shape| shape.name()).collect::<String>());
+        let marks = |row: &[bool]| -> Vec<&str> { row.iter().map(|&covered| if covered { "O" } else { "X" }).collect() };
+        let counts = self.solution_counts();
+        let any = self.any_count();
+        let total = self.queues.len();
+        let percent = |count: usize| count as f64 / total as f64 * 100.0;
+
+        match format {
+            OutputFormat::Text => {
+                for (solution, (fumen, &count)) in fumens.iter().zip(&counts).enumerate() {
+                    writeln!(to, "{solution:>4} {:>7.2}% {count:>8}/{total} {fumen}", percent(count))?;
+                }
+                writeln!(to, "any solution: {:.2}% ({any}/{total})", percent(any))?;
+                for (queue, row) in queues.zip(&self.covered) {
+                    writeln!(to, "{queue} {}", marks(row).concat())?;
+                }
+            }
+            OutputFormat::Json => {
+                let solutions: Vec<serde_json::Value> = fumens
+                    .iter()
+                    .zip(&counts)
+                    .map(|(fumen, count)| serde_json::json!({ "fumen": fumen, "covered": count }))
+                    .collect();
+                let queues: Vec<serde_json::Value> = queues
+                    .zip(&self.covered)
+                    .map(|(queue, row)| serde_json::json!({ "queue": queue, "covered": row }))
+                    .collect();
+                serde_json::to_writer(&mut to, &serde_json::json!({
+                    "solutions": solutions,
+                    "any": any,
+                    "total": total,
+                    "queues": queues,
+                }))?;
+                writeln!(to)?;
+            }
+            OutputFormat::Csv => {
+                for (fumen, count) in fumens.iter().zip(&counts) {
+                    writeln!(to, "# {fumen}: {count}/{total}")?;
+                }
+                writeln!(to, "# any: {any}/{total}")?;
+                writeln!(to, "queue,{}", fumens.join(","))?;
+                for (queue, row) in queues.zip(&self.covered) {
+                    writeln!(to, "{queue},{}", marks(row).join(","))?;
+                }
+            }
+        }
+        to.flush()
+    }
+}
+
+#[test]
+fn from_fumens() {
+    use std::str::FromStr;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let queue = CombinatoricQueue::from_str("[OOOOIIT]p7").unwrap();
+    let found = crate::path::find_solutions(&gigapan, board, &queue, true);
+
+    // Solutions survive a round trip through fumen, and the matrix agrees
+    // with the counts from the path finder.
+    let pages = found.iter().map(|solution| (&solution.board, None));
+    let solutions = crate::fumens::decode_solutions(&encode_broken_boards(pages)).unwrap();
+    assert!(found.iter().map(|solution| &solution.board).eq(solutions.iter()));
+
+    let matrix = CoverMatrix::compute(solutions, &queue, true);
+    let counts: Vec<usize> = found.iter().map(|solution| solution.covered).collect();
+    assert_eq!(matrix.solution_counts(), counts);
+    assert_eq!(matrix.any_count(), queue.queue_count());
+
+    let mut json = Vec::new();
+    matrix.write(OutputFormat::Json, &mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    let covered: Vec<usize> = json["solutions"].as_array().unwrap().iter().map(|solution| solution["covered"].as_u64().unwrap() as usize).collect();
+    assert_eq!(covered, counts);
+    assert_eq!(json["any"], queue.queue_count());
+    assert_eq!(json["queues"].as_array().unwrap().len(), matrix.queues.len());
+}
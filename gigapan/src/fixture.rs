@@ -0,0 +1,12 @@
+//! The board most of the tests run on, which is small enough for its gigapan
+//! to be built in the test.
+
+use legal_boards::boardgraph::{subgraph, FrozenGigapan};
+use srs_4l::gameplay::Board;
+
+/// The left two columns of the bottom two rows are empty, which is the
+/// v115@ThH8BeH8JeAgH fumen
+pub(crate) fn small_board() -> (Board, FrozenGigapan) {
+    let board = Board(0b1111111100_1111111100);
+    (board, subgraph(&[board]).freeze())
+}
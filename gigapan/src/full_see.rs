@@ -0,0 +1,352 @@
+//! Full see success counts for every board of a graph, so the full see chance
+//! of a board is a lookup instead of a search.
+//!
+//! Seeing the whole queue, whether a queue passes from some point depends only
+//! on the board, the piece in hold and the pieces left in the current 7-bag.
+//! The table keeps how many ways the queue can go on from each of those which
+//! pass.  Queues have one more piece than it takes to fill the board, counting
+//! the one in hold, like the patterns of
+//! [`limited_see_chance`](crate::calculate::limited_see_chance).
+//!
+//! Building the table needs every passing queue of two stages of boards at
+//! once, which grows by a factor of up to 7 with each piece, so it's only
+//! practical for boards a few pieces from a perfect clear.
+
+use std::io::{self, Read, Write};
+use std::time::Instant;
+
+use bitvec::vec::BitVec;
+use hashbrown::{HashMap, HashSet};
+use legal_boards::boardgraph::{FrozenGigapan, GigapanLookup};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use srs_4l::gameplay::{Board, Shape};
+
+/// The pieces left in the current bag, one bit per shape
+type BagMask = u8;
+
+/// A bag with nothing left is the same as a new one, so it's stored as full
+const FULL_BAG: BagMask = 0b1111111;
+const BAGS: usize = FULL_BAG as usize;
+/// Nothing in hold, then each shape
+const HOLDS: usize = 8;
+
+pub struct FullSeeTable {
+    pub use_hold: bool,
+    pub two_line: bool,
+    /// Passing queues by board, then hold and bag
+    counts: HashMap<Board, Box<[u32]>>,
+}
+
+fn index(hold: Option<Shape>, bag: BagMask) -> usize {
+    hold.map_or(0, |shape| shape as usize + 1) * BAGS + bag as usize - 1
+}
+
+fn bag_mask(bag: &[Shape]) -> BagMask {
+    match bag.iter().fold(0, |mask, &shape| mask | 1 << shape as u8) {
+        0 => FULL_BAG,
+        mask => mask,
+    }
+}
+
+fn take(bag: BagMask, shape: Shape) -> BagMask {
+    match bag & !(1 << shape as u8) {
+        0 => FULL_BAG,
+        left => left,
+    }
+}
+
+/// How many ways `len` more pieces can come out of 7-bags, with `left`
+/// pieces left in the current one
+fn continuations(left: usize, len: usize) -> usize {
+    let drawn = len.min(left);
+    let here: usize = (left - drawn + 1..=left).product();
+    if len > drawn {
+        here * continuations(7, len - drawn)
+    } else {
+        here
+    }
+}
+
+/// Pieces it takes to fill the board
+fn pieces_left(board: Board) -> usize {
+    (40 - board.0.count_ones() as usize) / 4
+}
+
+/// Bytes of passing queues kept for one board needing `pieces` pieces
+fn board_bytes(pieces: usize) -> usize {
+    let mut bits = 0;
+    for hold in [false, true] {
+        let slots = if hold { HOLDS - 1 } else { 1 };
+        let len = pieces + !hold as usize;
+        for bag in 1..=FULL_BAG {
+            bits += slots * continuations(bag.count_ones() as usize, len).div_ceil(usize::BITS as usize);
+        }
+    }
+    bits * std::mem::size_of::<usize>()
+}
+
+/// The pattern for a queue of `pieces` starting with `left` pieces of the
+/// current 7-bag, like `*p4,*p7`
+pub fn phase_pattern(pieces: usize, left: usize) -> String {
+    let mut bags = vec![left.min(pieces)];
+    let mut rest = pieces - bags[0];
+    while rest > 0 {
+        bags.push(rest.min(7));
+        rest -= rest.min(7);
+    }
+    let bags: Vec<String> = bags.iter().map(|count| format!("*p{count}")).collect();
+    bags.join(",")
+}
+
+impl FullSeeTable {
+    /// Count the passing queues from every board reachable from `starts`, for
+    /// every hold and bag.  Boards are done by how many pieces they need, from
+    /// the full board back, keeping the passing queues themselves only for the
+    /// boards one piece on.  This fails without counting anything if those of
+    /// two stages would take more than `max_bytes`.
+    pub fn build(
+        gigapan: &FrozenGigapan,
+        starts: &[Board],
+        use_hold: bool,
+        two_line: bool,
+        max_bytes: usize,
+    ) -> Result<Self, String> {
+        let table = FullSeeTable { use_hold, two_line, counts: HashMap::new() };
+        let mut stages: Vec<Vec<Board>> = vec![Vec::new(); 11];
+        let mut seen = HashSet::new();
+        let mut stack = Vec::new();
+        for &start in starts {
+            if start.0.count_ones() % 4 != 0 || gigapan.edges(start).is_none() {
+                return Err(format!("no perfect clear is possible from {start}"));
+            }
+            stack.push(start);
+        }
+        while let Some(board) = stack.pop() {
+            if !seen.insert(board) {
+                continue;
+            }
+            stages[pieces_left(board)].push(board);
+            if let (false, Some(edges)) = (table.is_done(board), gigapan.edges(board)) {
+                stack.extend(edges.children());
+            }
+        }
+
+        let stage_bytes: Vec<usize> = stages.iter().enumerate().map(|(pieces, boards)| boards.len() * board_bytes(pieces)).collect();
+        if let Some((pieces, bytes)) = stage_bytes.windows(2).map(|pair| pair[0] + pair[1]).enumerate().max_by_key(|&(_, bytes)| bytes) {
+            if bytes > max_bytes {
+                return Err(format!(
+                    "the boards needing {pieces} and {} pieces need {} MiB of passing queues, more than the {} MiB allowed",
+                    pieces + 1,
+                    bytes >> 20,
+                    max_bytes >> 20,
+                ));
+            }
+        }
+
+        let mut counts = HashMap::new();
+        let mut previous: HashMap<Board, Vec<BitVec>> = HashMap::new();
+        for (pieces, boards) in stages.into_iter().enumerate() {
+            let instant = Instant::now();
+            let count = boards.len();
+            let stage: Vec<(Board, Vec<BitVec>)> = boards
+                .into_par_iter()
+                .map(|board| (board, table.passing_queues(gigapan, board, pieces, &previous)))
+                .collect();
+            for (board, queues) in &stage {
+                counts.insert(*board, queues.iter().map(|queues| queues.count_ones() as u32).collect());
+            }
+            if count > 0 {
+                eprintln!("counted {count} boards needing {pieces} pieces in {:.3}s", instant.elapsed().as_secs_f64());
+            }
+            previous = stage.into_iter().collect();
+        }
+
+        Ok(FullSeeTable { counts, ..table })
+    }
+
+    fn is_done(&self, board: Board) -> bool {
+        board == Board::full() || (self.two_line && board == Board::half())
+    }
+
+    /// The passing queues from `board` for every hold and bag, in the order
+    /// the pieces can come out of the bags, from those of the boards after it
+    fn passing_queues(
+        &self,
+        gigapan: &FrozenGigapan,
+        board: Board,
+        pieces: usize,
+        previous: &HashMap<Board, Vec<BitVec>>,
+    ) -> Vec<BitVec> {
+        let done = self.is_done(board);
+        // the passing queues of the boards after placing each shape
+        let children: Vec<Vec<&Vec<BitVec>>> = Shape::ALL
+            .into_iter()
+            .map(|shape| match gigapan.edges(board) {
+                Some(edges) if !done => edges.get(shape).filter_map(|child| previous.get(&child)).collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+
+        let mut queues = vec![BitVec::new(); HOLDS * BAGS];
+        // holding first, since with nothing in hold the next piece can be held
+        for hold in Shape::ALL.map(Some).into_iter().chain([None]) {
+            let len = pieces + hold.is_none() as usize;
+            for bag in 1..=FULL_BAG {
+                let left = bag.count_ones() as usize;
+                if done {
+                    queues[index(hold, bag)] = BitVec::repeat(true, continuations(left, len));
+                    continue;
+                }
+
+                let mut passing = BitVec::new();
+                for shape in Shape::ALL.into_iter().filter(|&shape| bag & 1 << shape as u8 != 0) {
+                    let after = take(bag, shape);
+                    let mut next: BitVec = BitVec::repeat(false, continuations(after.count_ones() as usize, len - 1));
+                    // both start at the first bit, so whole words line up
+                    let mut add = |queues: &BitVec| {
+                        for (word, child_word) in next.as_raw_mut_slice().iter_mut().zip(queues.as_raw_slice()) {
+                            *word |= child_word;
+                        }
+                    };
+                    for child in &children[shape as usize] {
+                        add(&child[index(hold, after)]);
+                    }
+                    match hold {
+                        Some(held) if self.use_hold && held != shape => {
+                            for child in &children[held as usize] {
+                                add(&child[index(Some(shape), after)]);
+                            }
+                        }
+                        None if self.use_hold => add(&queues[index(Some(shape), after)]),
+                        _ => {}
+                    }
+                    passing.extend_from_bitslice(&next);
+                }
+                queues[index(hold, bag)] = passing;
+            }
+        }
+        queues
+    }
+
+    /// Boards in the table
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Passing queues from `board` with `hold`, when `bag` is what's left of
+    /// the current 7-bag, or `None` if the board isn't in the table
+    pub fn passing(&self, board: Board, hold: Option<Shape>, bag: &[Shape]) -> Option<usize> {
+        let counts = self.counts.get(&board)?;
+        Some(counts[index(hold, bag_mask(bag))] as usize)
+    }
+
+    /// Every way the queue can go on from `board` with `hold` and `bag`
+    pub fn total(&self, board: Board, hold: Option<Shape>, bag: &[Shape]) -> usize {
+        let len = pieces_left(board) + hold.is_none() as usize;
+        continuations(bag_mask(bag).count_ones() as usize, len)
+    }
+
+    /// The full see chance of a perfect clear from `board` with `left` pieces
+    /// of the current 7-bag to come, over every bag they can be, as passing
+    /// and total queues.  This is the pattern of [`phase_pattern`], where the
+    /// first piece starts in hold with `init_hold`.
+    pub fn phase(&self, board: Board, left: usize, init_hold: bool) -> Option<(usize, usize)> {
+        let counts = self.counts.get(&board)?;
+        let pieces = pieces_left(board);
+        let (mut passing, mut total) = (0, 0);
+        for bag in (1..=FULL_BAG).filter(|bag| bag.count_ones() as usize == left) {
+            if init_hold {
+                for shape in Shape::ALL.into_iter().filter(|&shape| bag & 1 << shape as u8 != 0) {
+                    let after = take(bag, shape);
+                    passing += counts[index(Some(shape), after)] as usize;
+                    total += continuations(after.count_ones() as usize, pieces);
+                }
+            } else {
+                passing += counts[index(None, bag)] as usize;
+                total += continuations(left, pieces + 1);
+            }
+        }
+        Some((passing, total))
+    }
+
+    /// Write the table as leb128 numbers: the settings, then each board as
+    /// its difference from the one before, followed by its counts
+    pub fn write(&self, mut to: impl Write) -> io::Result<()> {
+        let settings = self.use_hold as u64 | (self.two_line as u64) << 1;
+        leb128::write::unsigned(&mut to, settings)?;
+        leb128::write::unsigned(&mut to, self.counts.len() as u64)?;
+
+        let mut boards: Vec<&Board> = self.counts.keys().collect();
+        boards.sort_unstable();
+        let mut current = 0;
+        for board in boards {
+            leb128::write::unsigned(&mut to, board.0 - current)?;
+            current = board.0;
+            for &count in self.counts[board].iter() {
+                leb128::write::unsigned(&mut to, count as u64)?;
+            }
+        }
+        to.flush()
+    }
+
+    /// Read a table written by [`write`](Self::write)
+    pub fn read(mut from: impl Read) -> io::Result<Self> {
+        let mut number = || {
+            leb128::read::unsigned(&mut from).map_err(|error| match error {
+                leb128::read::Error::IoError(error) => error,
+                error => io::Error::new(io::ErrorKind::InvalidData, error),
+            })
+        };
+        let settings = number()?;
+        let len = number()? as usize;
+
+        let mut counts = HashMap::with_capacity(len);
+        let mut current = 0;
+        for _ in 0..len {
+            current += number()?;
+            let board_counts = (0..HOLDS * BAGS)
+                .map(|_| Ok(number()? as u32))
+                .collect::<io::Result<Box<[u32]>>>()?;
+            counts.insert(Board(current), board_counts);
+        }
+
+        Ok(FullSeeTable { use_hold: settings & 1 != 0, two_line: settings & 2 != 0, counts })
+    }
+}
+
+#[test]
+fn matches_chance() {
+    use std::str::FromStr;
+
+    use crate::calculate::limited_see_chance;
+    use crate::queue::CombinatoricQueue;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    assert!(FullSeeTable::build(&gigapan, &[board], true, false, 1 << 20).is_err());
+    let table = FullSeeTable::build(&gigapan, &[board], true, false, 1 << 30).unwrap();
+
+    // seeing the whole queue, for a new bag, part way through one, and
+    // without starting with the first piece in hold
+    for (left, init_hold) in [(7, true), (3, true), (7, false)] {
+        let pattern = phase_pattern(7, left);
+        let queue = CombinatoricQueue::from_str(&pattern).unwrap();
+        let previews = queue.get_counted_bags().len() - 1 - init_hold as usize;
+        let chance = limited_see_chance(&gigapan, board, &queue, previews, init_hold, true, false, false).unwrap();
+        assert_eq!(table.phase(board, left, init_hold), Some((chance.passing, chance.total)), "{pattern}");
+    }
+
+    let queue = CombinatoricQueue::from_str("O,*p6").unwrap();
+    let chance = limited_see_chance(&gigapan, board, &queue, 5, true, true, false, false).unwrap();
+    assert_eq!(table.passing(board, Some(Shape::O), &[]), Some(chance.passing));
+    assert_eq!(table.total(board, Some(Shape::O), &[]), chance.total);
+
+    let mut written = Vec::new();
+    table.write(&mut written).unwrap();
+    let read = FullSeeTable::read(written.as_slice()).unwrap();
+    assert_eq!((read.len(), read.use_hold, read.two_line), (table.len(), true, false));
+    assert_eq!(read.phase(board, 4, true), table.phase(board, 4, true));
+}
@@ -0,0 +1,226 @@
+//! Encoding boards as fumens, so they can be looked at.
+
+use fumen::{CellColor, Fumen, Page};
+use srs_4l::brokenboard::{BrokenBoard, BrokenPiece};
+use srs_4l::gameplay::{Board, Orientation, Shape, PIECE_SHAPES};
+
+pub fn board_page(board: Board, comment: Option<String>) -> Page {
+    let mut page = Page {
+        comment,
+        ..Page::default()
+    };
+    for idx in 0..40 {
+        if board.0 & (1 << idx) != 0 {
+            page.field[idx / 10][idx % 10] = CellColor::Grey;
+        }
+    }
+    page
+}
+
+/// Encode a list of boards as the pages of one fumen
+pub fn encode_boards(pages: impl IntoIterator<Item = (Board, Option<String>)>) -> String {
+    let mut fumen = Fumen::default();
+    for (board, comment) in pages {
+        fumen.pages.push(board_page(board, comment));
+    }
+    fumen.encode()
+}
+
+/// The board on the first page of a fumen, if it fits in the bottom 4 rows
+pub fn decode_fumen(encoded: &str) -> Option<u64> {
+    let fumen = Fumen::decode(encoded).ok()?;
+    let page: &Page = fumen.pages.first()?;
+
+    if page.field[4..] != [[CellColor::Empty; 10]; 19] || page.garbage_row != [CellColor::Empty; 10] {
+        return None;
+    }
+
+    let mut field = 0;
+    for idx in 0..40 {
+        let cell: CellColor = page.field[idx / 10][idx % 10];
+        let filled = cell != CellColor::Empty;
+        field |= (filled as u64) << idx;
+    }
+
+    Some(field)
+}
+
+fn shape_color(shape: Shape) -> CellColor {
+    match shape {
+        Shape::I => CellColor::I,
+        Shape::J => CellColor::J,
+        Shape::L => CellColor::L,
+        Shape::O => CellColor::O,
+        Shape::S => CellColor::S,
+        Shape::T => CellColor::T,
+        Shape::Z => CellColor::Z,
+    }
+}
+
+/// Encode a sequence of placements from `start` as the pages of one fumen.
+/// Each page colors the cells of the newly placed piece by its shape.
+pub fn encode_path(
+    start: Board,
+    comment: Option<String>,
+    steps: impl IntoIterator<Item = (Shape, Board, Option<String>)>,
+) -> String {
+    let mut fumen = Fumen::default();
+    let mut page = board_page(start, comment);
+    let mut board = start;
+    fumen.pages.push(page.clone());
+    for (shape, child, comment) in steps {
+        for idx in 0..40 {
+            if (child.0 & !board.0) & (1 << idx) != 0 {
+                page.field[idx / 10][idx % 10] = shape_color(shape);
+            }
+        }
+        page.comment = comment;
+        fumen.pages.push(page.clone());
+        board = child;
+    }
+    fumen.encode()
+}
+
+/// Encode unrelated moves as the pages of one fumen.  Each page shows its
+/// board with the cells of the placed piece colored by its shape, if a piece
+/// was placed.
+pub fn encode_placements(
+    pages: impl IntoIterator<Item = (Board, Option<(Shape, Board)>, Option<String>)>,
+) -> String {
+    let mut fumen = Fumen::default();
+    for (board, placed, comment) in pages {
+        let mut page = board_page(board, comment);
+        if let Some((shape, child)) = placed {
+            for idx in 0..40 {
+                if (child.0 & !board.0) & (1 << idx) != 0 {
+                    page.field[idx / 10][idx % 10] = shape_color(shape);
+                }
+            }
+        }
+        fumen.pages.push(page);
+    }
+    fumen.encode()
+}
+
+/// Encode solutions as the pages of one fumen, with cleared lines in place
+/// and each piece colored by its shape
+pub fn encode_broken_boards<'a>(
+    pages: impl IntoIterator<Item = (&'a BrokenBoard, Option<String>)>,
+) -> String {
+    let mut fumen = Fumen::default();
+    for (board, comment) in pages {
+        let mut page = board_page(board.to_broken_bitboard(), comment);
+        for piece in &board.pieces {
+            let cells = piece.board();
+            for idx in 0..40 {
+                if cells.0 & (1 << idx) != 0 {
+                    page.field[idx / 10][idx % 10] = shape_color(piece.shape);
+                }
+            }
+        }
+        fumen.pages.push(page);
+    }
+    fumen.encode()
+}
+
+fn color_shape(color: CellColor) -> Option<Shape> {
+    Shape::ALL.into_iter().find(|&shape| shape_color(shape) == color)
+}
+
+/// Every way to put a piece of `shape` into the bottom four rows, with its
+/// rows spread over any choice of rows, as cells and the broken piece
+fn broken_pieces(shape: Shape) -> Vec<(u64, BrokenPiece)> {
+    let orientations = [Orientation::North, Orientation::East, Orientation::South, Orientation::West];
+    let mut pieces = Vec::new();
+    for orientation in orientations {
+        if orientation.canonical(shape) != orientation {
+            continue;
+        }
+        let mask = PIECE_SHAPES[shape as usize][orientation as usize];
+        let height = (63 - mask.leading_zeros()) / 10 + 1;
+        let runs: Vec<u64> = (0..height).map(|row| (mask >> (row * 10)) & 0b1111111111).collect();
+        let width = runs.iter().map(|run| 64 - run.leading_zeros()).max().unwrap();
+
+        for rows in 0..16u8 {
+            if rows.count_ones() != height {
+                continue;
+            }
+            let row_indices: Vec<u32> = (0..4).filter(|row| rows & (1 << row) != 0).collect();
+            for col in 0..=(10 - width) {
+                let cells = runs
+                    .iter()
+                    .zip(&row_indices)
+                    .fold(0, |cells, (run, row)| cells | run << (row * 10 + col));
+                let piece = BrokenPiece {
+                    low_mino: cells.trailing_zeros() as u8,
+                    shape,
+                    orientation,
+                    rows,
+                };
+                pieces.push((cells, piece));
+            }
+        }
+    }
+    pieces
+}
+
+/// Split the colored cells into pieces of their colors, lowest cell first
+fn split_pieces(cells: &[u64; 7], candidates: &[Vec<(u64, BrokenPiece)>], pieces: &mut Vec<BrokenPiece>) -> bool {
+    let remaining = cells.iter().fold(0, |all, cells| all | cells);
+    if remaining == 0 {
+        return true;
+    }
+    let lowest = remaining.trailing_zeros();
+    let shape = Shape::ALL.into_iter().find(|&shape| cells[shape as usize] & (1 << lowest) != 0).unwrap();
+
+    for &(piece_cells, piece) in &candidates[shape as usize] {
+        if piece_cells.trailing_zeros() != lowest || piece_cells & cells[shape as usize] != piece_cells {
+            continue;
+        }
+        let mut rest = *cells;
+        rest[shape as usize] ^= piece_cells;
+        pieces.push(piece);
+        if split_pieces(&rest, candidates, pieces) {
+            return true;
+        }
+        pieces.pop();
+    }
+    false
+}
+
+/// Decode every page of a fumen as a solution.  Grey cells are the starting
+/// garbage, and colored cells are split into pieces of their color, which may
+/// be broken over cleared lines.
+pub fn decode_solutions(encoded: &str) -> Option<Vec<BrokenBoard>> {
+    let fumen = Fumen::decode(encoded).ok()?;
+    let candidates: Vec<Vec<(u64, BrokenPiece)>> = Shape::ALL.into_iter().map(broken_pieces).collect();
+
+    fumen
+        .pages
+        .iter()
+        .map(|page| {
+            if page.field[4..] != [[CellColor::Empty; 10]; 19] {
+                return None;
+            }
+            let mut garbage = 0;
+            let mut cells = [0; 7];
+            for idx in 0..40 {
+                match page.field[idx / 10][idx % 10] {
+                    CellColor::Empty => {}
+                    CellColor::Grey => garbage |= 1 << idx,
+                    color => cells[color_shape(color)? as usize] |= 1 << idx,
+                }
+            }
+
+            let mut pieces = Vec::new();
+            if !split_pieces(&cells, &candidates, &mut pieces) {
+                return None;
+            }
+            let filled = cells.iter().fold(garbage, |all, cells| all | cells);
+            let mut board = BrokenBoard::from_garbage(filled);
+            board.pieces = pieces.into_iter().collect();
+            board.pieces.sort_unstable();
+            board.is_valid().then_some(board)
+        })
+        .collect()
+}
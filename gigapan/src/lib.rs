@@ -1,2 +1,19 @@
 pub mod queue;
-pub mod calculate;
\ No newline at end of file
+pub mod calculate;
+pub mod stats;
+pub mod fumens;
+pub mod verify;
+pub mod solutions;
+pub mod path;
+pub mod cover;
+pub mod minimal;
+pub mod sweep;
+pub mod batch;
+pub mod serve;
+pub mod advise;
+pub mod strategy;
+pub mod checkpoint;
+pub mod full_see;
+
+#[cfg(test)]
+mod fixture;
@@ -1,86 +1,779 @@
 
-mod queue;
-mod calculate;
+use gigapan::{advise, batch, calculate, checkpoint, cover, full_see, fumens, minimal, path, queue, serve, solutions, stats, strategy, sweep, verify};
+use std::fs::File;
+use std::io::BufWriter;
 use std::str::FromStr;
-use srs_4l::gameplay::Board;
+use srs_4l::{brokenboard::BrokenBoard, gameplay::{Board, Shape}};
+use legal_boards::boardgraph::{mirror_expand, FrozenGigapan, GigapanLookup};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// SFinder queue input
-    #[arg(short, long, default_value = "", required=false)]
-    queue: String,
+    /// Directory of the gigapan shards
+    #[arg(long, global = true, default_value = "./gigapan_shards")]
+    data_dir: String,
 
-    /// How many previews gigapan uses
-    #[arg(short, long, default_value_t = 5)]
-    previews: usize,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate the gigapan and write it to the data directory
+    Generate {
+        /// Store only one of each board and its mirror
+        #[arg(long, action)]
+        mirror: bool,
+
+        /// Also write the list of legal boards to this file
+        #[arg(long)]
+        legal_boards: Option<String>,
+    },
+
+    /// Calculate the chance of a perfect clear with limited see
+    Chance {
+        /// SFinder queue input
+        #[arg(short, long)]
+        queue: String,
+
+        /// How many previews gigapan uses
+        #[arg(short, long, default_value_t = 5)]
+        previews: usize,
+
+        /// Fumen input of the board
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: String,
+
+        /// Culled
+        #[arg(short, long, action)]
+        culled: bool,
+
+        /// Restrict the use of hold
+        #[arg(short, long, action)]
+        no_hold: bool,
+
+        #[arg(short, long, action)]
+        /// Start off simulations with no piece in hold
+        blank_start: bool,
+
+        /// Start off simulations with this piece in hold, like `(T)` in the queue
+        #[arg(long, value_parser = queue::parse_shape)]
+        hold: Option<Shape>,
+
+        #[arg(short, long, action)]
+        /// Consider 2-line PCs
+        two_line: bool,
+
+        /// Write the results to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Format of the results
+        #[arg(long, value_enum, default_value_t = calculate::OutputFormat::Text)]
+        format: calculate::OutputFormat,
+
+        /// Break the chance down by the first pieces of the queue, this many deep
+        #[arg(long, default_value_t = 0)]
+        tree: usize,
+
+        /// Also write a fumen of how each full queue is solved to this file
+        #[arg(long)]
+        solutions: Option<String>,
+
+        /// Only solve this many random full queues
+        #[arg(long)]
+        sample: Option<usize>,
+
+        /// Seed for choosing the queues to solve
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// Stop after this many seconds, giving bounds on the chance
+        #[arg(long, value_parser = parse_seconds)]
+        time_limit: Option<std::time::Duration>,
+
+        /// Stop once the bounds on the chance are within this many percent
+        #[arg(long)]
+        gap: Option<f64>,
+
+        /// Keep each queue's result in this file as it's done, and skip the
+        /// queues already in it
+        #[arg(long)]
+        checkpoint: Option<String>,
+    },
+
+    /// Calculate the chance for a range of preview counts in one run
+    Sweep {
+        /// SFinder queue input
+        #[arg(short, long)]
+        queue: String,
+
+        /// Preview counts to run, like `1-7` or `5`
+        #[arg(short, long, default_value = "1-7", value_parser = parse_previews)]
+        previews: std::ops::RangeInclusive<usize>,
+
+        /// Fumen input of the board
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: String,
+
+        /// Culled
+        #[arg(short, long, action)]
+        culled: bool,
+
+        /// Restrict the use of hold
+        #[arg(short, long, action)]
+        no_hold: bool,
+
+        /// Run every preview count both with and without hold
+        #[arg(long, action, conflicts_with = "no_hold")]
+        both: bool,
+
+        #[arg(short, long, action)]
+        /// Start off simulations with no piece in hold
+        blank_start: bool,
+
+        /// Start off simulations with this piece in hold, like `(T)` in the queue
+        #[arg(long, value_parser = queue::parse_shape)]
+        hold: Option<Shape>,
+
+        #[arg(short, long, action)]
+        /// Consider 2-line PCs
+        two_line: bool,
+
+        /// Write the table to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Format of the table
+        #[arg(long, value_enum, default_value_t = calculate::OutputFormat::Text)]
+        format: calculate::OutputFormat,
+    },
+
+    /// Rank every move from a position part way through a queue by the chance after it
+    Advise {
+        /// SFinder queue input, from the current piece on
+        #[arg(short, long)]
+        queue: String,
+
+        /// The current piece and the previews, like `TIO`, which must start the queue
+        #[arg(short, long)]
+        seen: String,
+
+        /// Fumen input of the board
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: String,
+
+        /// The piece in hold, if any, like `(T)` in the queue
+        #[arg(long, value_parser = queue::parse_shape)]
+        hold: Option<Shape>,
+
+        /// Culled
+        #[arg(short, long, action)]
+        culled: bool,
+
+        /// Restrict the use of hold
+        #[arg(short, long, action)]
+        no_hold: bool,
+
+        #[arg(short, long, action)]
+        /// Consider 2-line PCs
+        two_line: bool,
+
+        /// Write the moves to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Format of the moves
+        #[arg(long, value_enum, default_value_t = calculate::OutputFormat::Text)]
+        format: calculate::OutputFormat,
+    },
+
+    /// Export the best move at every point of the queue as a decision tree
+    Strategy {
+        /// SFinder queue input
+        #[arg(short, long)]
+        queue: String,
+
+        /// How many previews gigapan uses
+        #[arg(short, long, default_value_t = 5)]
+        previews: usize,
+
+        /// Fumen input of the board
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: String,
+
+        /// Culled
+        #[arg(short, long, action)]
+        culled: bool,
+
+        /// Restrict the use of hold
+        #[arg(short, long, action)]
+        no_hold: bool,
+
+        #[arg(short, long, action)]
+        /// Start off simulations with no piece in hold
+        blank_start: bool,
+
+        /// Start off simulations with this piece in hold, like `(T)` in the queue
+        #[arg(long, value_parser = queue::parse_shape)]
+        hold: Option<Shape>,
+
+        #[arg(short, long, action)]
+        /// Consider 2-line PCs
+        two_line: bool,
+
+        /// Only follow this many moves, instead of every move to the end
+        #[arg(long)]
+        depth: Option<usize>,
+
+        /// Write the tree to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Format of the tree, with fumen giving one page per position
+        #[arg(long, value_enum, default_value_t = strategy::StrategyFormat::Text)]
+        format: strategy::StrategyFormat,
+    },
+
+    /// Run every chance job in a file of JSON lines against one loaded graph
+    Batch {
+        /// File with one job per line, like `{"fumen": "v115@vhAAgH", "pattern": "*p7", "previews": 3}`
+        file: String,
+
+        /// Write the results to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Format of the results, with json giving one line per job
+        #[arg(long, value_enum, default_value_t = calculate::OutputFormat::Json)]
+        format: calculate::OutputFormat,
+    },
+
+    /// Load the graph once and answer chance, path and cover queries as JSON
+    /// lines on stdin, with results and progress as JSON lines on stdout
+    Serve {
+        /// Fumens of the boards to build the graph for when shards are missing
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: Vec<String>,
+
+        /// Milliseconds between progress events of running chance queries
+        #[arg(long, default_value_t = 500)]
+        progress_ms: u64,
+    },
+
+    /// List every perfect clear solution for a board and pattern
+    Path {
+        /// SFinder queue input, with one more piece than the solutions
+        #[arg(short, long)]
+        queue: String,
+
+        /// Fumen input of the board
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: String,
 
-    /// Fumen input of the board
-    #[arg(short, long, default_value = "v115@vhAAgH")]
-    fumen: String,
+        /// Restrict the use of hold
+        #[arg(short, long, action)]
+        no_hold: bool,
 
-    /// Culled
-    #[arg(short, long, action)]
-    culled: bool,
+        /// Write the solutions to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
 
-    /// Restrict the use of hold
-    #[arg(short, long, action)]
-    no_hold: bool,
+        /// Format of the solutions
+        #[arg(long, value_enum, default_value_t = path::PathFormat::Csv)]
+        format: path::PathFormat,
+    },
 
-    #[arg(short, long, action)]
-    /// Start off simulations with no piece in hold
-    blank_start: bool,
+    /// Check which queues of a pattern can build which solutions
+    Cover {
+        /// SFinder queue input
+        #[arg(short, long)]
+        queue: String,
 
-    #[arg(short, long, action)]
-    /// Consider 2-line PCs
-    two_line: bool
+        /// Fumen of the solutions, one per page.  Without any, every solution
+        /// from the board is enumerated
+        #[arg(short, long)]
+        solutions: Vec<String>,
 
+        /// Fumen input of the board, when enumerating solutions
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: String,
+
+        /// Restrict the use of hold
+        #[arg(short, long, action)]
+        no_hold: bool,
+
+        /// Write the summary and matrix to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Format of the matrix
+        #[arg(long, value_enum, default_value_t = calculate::OutputFormat::Csv)]
+        format: calculate::OutputFormat,
+    },
+
+    /// Find the fewest solutions covering every solvable queue, or the best few
+    Minimal {
+        /// SFinder queue input
+        #[arg(short, long)]
+        queue: String,
+
+        /// Fumen of the candidate solutions, one per page.  Without any, every
+        /// solution from the board is a candidate
+        #[arg(short, long)]
+        solutions: Vec<String>,
+
+        /// Fumen input of the board, when enumerating solutions
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: String,
+
+        /// Restrict the use of hold
+        #[arg(short, long, action)]
+        no_hold: bool,
+
+        /// Find this many solutions covering the most queues instead
+        #[arg(long)]
+        best: Option<usize>,
+
+        /// How many search nodes to try before settling for the greedy answer
+        #[arg(long, default_value_t = 1_000_000)]
+        budget: usize,
+
+        /// Write the chosen solutions to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Format of the chosen solutions
+        #[arg(long, value_enum, default_value_t = calculate::OutputFormat::Text)]
+        format: calculate::OutputFormat,
+    },
+
+    /// Print statistics about the graph
+    Stats {
+        /// How many boards to list for each statistic
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+    },
+
+    /// Export the graph to a file
+    Export {
+        /// File to export to
+        output: String,
+
+        /// Format of the exported graph
+        #[arg(long, value_enum, default_value_t = stats::ExportFormat::Csv)]
+        format: stats::ExportFormat,
+
+        /// Only export the part of the graph reachable from this fumen board
+        #[arg(short, long)]
+        fumen: Option<String>,
+    },
+
+    /// Check the graph for corrupted or missing data
+    Verify {
+        /// How many random nodes near the end of the graph to recompute
+        #[arg(long, default_value_t = 1000)]
+        sample: usize,
+
+        /// Seed for choosing the nodes to recompute
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// How many problems to list
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+    },
+
+    /// Count the queues which pass seeing the whole queue from every board
+    /// reachable from the boards, for every hold and 7-bag state, and write
+    /// them to a table
+    FullSeeTable {
+        /// File to write the table to
+        output: String,
+
+        /// Fumen inputs of the boards to start from
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: Vec<String>,
+
+        /// Restrict the use of hold
+        #[arg(short, long, action)]
+        no_hold: bool,
+
+        #[arg(short, long, action)]
+        /// Consider 2-line PCs
+        two_line: bool,
+
+        /// Most memory the passing queues being counted can take, in MiB
+        #[arg(long, default_value_t = 4096)]
+        max_memory: usize,
+    },
+
+    /// Look up the full see chance of a board in a table, for each number of
+    /// pieces left in the current 7-bag
+    FullSee {
+        /// Table written by full-see-table
+        table: String,
+
+        /// Fumen input of the board
+        #[arg(short, long, default_value = "v115@vhAAgH")]
+        fumen: String,
+
+        #[arg(short, long, action)]
+        /// Start off simulations with no piece in hold
+        blank_start: bool,
+    },
 }
 
 fn main() -> std::io::Result<()> {
 
     let args = Args::parse();
-    if args.queue == ""{
-        return legal_boards::create_gigapan();
-    }
+    let data_dir = args.data_dir.as_str();
+    match args.command {
+        Command::Generate { mirror, legal_boards } => {
+            legal_boards::create_gigapan(data_dir, mirror)?;
+            if let Some(path) = legal_boards {
+                legal_boards::create_legal_boards(&path, mirror)?;
+            }
+            Ok(())
+        }
+        Command::Chance { queue, previews, fumen, culled, no_hold, blank_start, hold, two_line, output, format, tree, solutions, sample, seed, time_limit, gap, checkpoint } => {
+            let board = Board(fumens::decode_fumen(&fumen).expect("valid fumen"));
+            let mut queue = queue::CombinatoricQueue::from_str(&queue).expect("valid queue");
+            if let Some(hold) = hold {
+                queue.set_hold(hold);
+            }
+            let init_hold = !blank_start || queue.hold().is_some();
+            let giga = load_for(data_dir, &[board]);
+            if giga.edges(board).is_none() {
+                eprintln!("no perfect clear is possible from {board}");
+                return Ok(());
+            }
+
+            eprintln!("running:{board} {}", queue);
+            let run = format!("{} {queue} previews={previews} init_hold={init_hold} use_hold={} culled={culled} two_line={two_line}", fumens::encode_boards([(board, None)]), !no_hold);
+            let checkpoint = match checkpoint {
+                Some(path) => Some(checkpoint::Checkpoint::open(&path, &run)?),
+                None => None,
+            };
+            let progress = calculate::Progress::with_stop(calculate::Stop { time: time_limit, gap });
+            let options = calculate::ChanceOptions {
+                previews,
+                init_hold,
+                use_hold: !no_hold,
+                culled,
+                two_line,
+                checkpoint: checkpoint.as_ref(),
+                progress: Some(&progress),
+            };
+            let result = match calculate::limited_see_chance_watched(&giga, board, &queue, &options) {
+                Some(result) => result,
+                None => return Ok(()),
+            };
+            match output {
+                Some(path) => result.write(format, tree, BufWriter::new(File::create(path)?))?,
+                None => result.write(format, tree, std::io::stdout().lock())?,
+            }
 
-    let board = Board(decode_fumen(&args.fumen).expect("valid fumen"));
-    let queue = queue::CombinatoricQueue::from_str(&args.queue).expect("valid queue");
-    
-    let giga = legal_boards::read_gigapan("./gigapan_shards").expect("unable to find gigapan shards! try without arguments to generate").freeze();
+            if let Some(path) = solutions {
+                let culled = culled.then(|| calculate::culled_boards(&giga, board, &queue, !no_hold));
+                let queues = solutions::pick_queues(&queue, sample, seed);
+                let solved = solutions::solve_queues(&giga, board, queues, culled.as_ref(), init_hold, !no_hold, two_line);
+                solutions::write_solutions(&solved, board, BufWriter::new(File::create(&path)?))?;
+                eprintln!("wrote {} solutions to {path}", solved.iter().filter(|solution| solution.steps.is_some()).count());
+            }
+            Ok(())
+        }
+        Command::Sweep { queue, previews, fumen, culled, no_hold, both, blank_start, hold, two_line, output, format } => {
+            let board = Board(fumens::decode_fumen(&fumen).expect("valid fumen"));
+            let mut queue = queue::CombinatoricQueue::from_str(&queue).expect("valid queue");
+            if let Some(hold) = hold {
+                queue.set_hold(hold);
+            }
+            let init_hold = !blank_start || queue.hold().is_some();
+            let giga = load_for(data_dir, &[board]);
+            if giga.edges(board).is_none() {
+                eprintln!("no perfect clear is possible from {board}");
+                return Ok(());
+            }
 
-    println!("giga loaded: {}",giga.len());
+            let holds: &[bool] = match (both, no_hold) {
+                (true, _) => &[true, false],
+                (false, no_hold) => &[!no_hold],
+            };
+            eprintln!("sweeping:{board} {}", queue);
+            let options = calculate::ChanceOptions { init_hold, culled, two_line, ..calculate::ChanceOptions::default() };
+            let sweep = match sweep::limited_see_sweep(&giga, board, &queue, previews, holds, &options) {
+                Some(sweep) => sweep,
+                None => return Ok(()),
+            };
+            match output {
+                Some(path) => sweep.write(format, BufWriter::new(File::create(path)?)),
+                None => sweep.write(format, std::io::stdout().lock()),
+            }
+        }
+        Command::Advise { queue, seen, fumen, hold, culled, no_hold, two_line, output, format } => {
+            let board = Board(fumens::decode_fumen(&fumen).expect("valid fumen"));
+            let mut queue = queue::CombinatoricQueue::from_str(&queue).expect("valid queue");
+            if let Some(hold) = hold {
+                queue.set_hold(hold);
+            }
+            let seen: Vec<Shape> = seen
+                .chars()
+                .map(|shape| queue::parse_shape(&shape.to_string()).expect("valid seen pieces"))
+                .collect();
+            let giga = load_for(data_dir, &[board]);
+            let culled = culled.then(|| calculate::culled_boards(&giga, board, &queue, !no_hold));
 
-    println!("running:{board} {}", queue);
-    calculate::limited_see_chance(&giga, board, &queue, args.previews, !args.blank_start, !args.no_hold, args.culled, args.two_line);
+            let advised = match advise::advise(&giga, board, &queue, &seen, !no_hold, culled.as_ref(), two_line) {
+                Ok(advised) => advised,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return Ok(());
+                }
+            };
+            match output {
+                Some(path) => advised.write(format, BufWriter::new(File::create(path)?)),
+                None => advised.write(format, std::io::stdout().lock()),
+            }
+        }
+        Command::Strategy { queue, previews, fumen, culled, no_hold, blank_start, hold, two_line, depth, output, format } => {
+            let board = Board(fumens::decode_fumen(&fumen).expect("valid fumen"));
+            let mut queue = queue::CombinatoricQueue::from_str(&queue).expect("valid queue");
+            if let Some(hold) = hold {
+                queue.set_hold(hold);
+            }
+            let init_hold = !blank_start || queue.hold().is_some();
+            let giga = load_for(data_dir, &[board]);
+            if giga.edges(board).is_none() {
+                eprintln!("no perfect clear is possible from {board}");
+                return Ok(());
+            }
 
-    Ok(())
-}
+            let options = calculate::ChanceOptions { previews, init_hold, use_hold: !no_hold, culled, two_line, ..calculate::ChanceOptions::default() };
+            let strategy = match strategy::limited_see_strategy(&giga, board, &queue, &options, depth) {
+                Ok(strategy) => strategy,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return Ok(());
+                }
+            };
+            match output {
+                Some(path) => strategy.write(format, BufWriter::new(File::create(path)?)),
+                None => strategy.write(format, std::io::stdout().lock()),
+            }
+        }
+        Command::Batch { file, output, format } => {
+            let jobs = match batch::read_jobs(std::io::BufReader::new(File::open(&file)?)) {
+                Ok(jobs) => jobs,
+                Err(error) => {
+                    eprintln!("unable to read {file}: {error}");
+                    return Ok(());
+                }
+            };
+            let mut boards: Vec<Board> = jobs.iter().filter_map(|job| job.board().ok()).collect();
+            boards.sort_unstable();
+            boards.dedup();
+            let giga = load_for(data_dir, &boards);
 
+            let failed = match output {
+                Some(path) => batch::run_jobs(&giga, &jobs, format, BufWriter::new(File::create(path)?))?,
+                None => batch::run_jobs(&giga, &jobs, format, std::io::stdout().lock())?,
+            };
+            eprintln!("ran {} jobs, {failed} failed", jobs.len());
+            Ok(())
+        }
+        Command::Serve { fumen, progress_ms } => {
+            let boards: Vec<Board> = fumen
+                .iter()
+                .map(|fumen| Board(fumens::decode_fumen(fumen).expect("valid fumen")))
+                .collect();
+            let giga = load_for(data_dir, &boards);
+            eprintln!("ready");
+            serve::serve(
+                &giga,
+                std::io::stdin().lock(),
+                std::io::stdout(),
+                std::time::Duration::from_millis(progress_ms),
+            )
+        }
+        Command::Path { queue, fumen, no_hold, output, format } => {
+            let board = Board(fumens::decode_fumen(&fumen).expect("valid fumen"));
+            let queue = queue::CombinatoricQueue::from_str(&queue).expect("valid queue");
+            let giga = load_for(data_dir, &[board]);
+            if giga.edges(board).is_none() {
+                eprintln!("no perfect clear is possible from {board}");
+                return Ok(());
+            }
 
-pub fn decode_fumen(encoded: &str) -> Option<u64> {
+            let instant = std::time::Instant::now();
+            let solutions = path::find_solutions(&giga, board, &queue, !no_hold);
+            eprintln!("found {} solutions in {:.3}s", solutions.len(), instant.elapsed().as_secs_f64());
+            let total = queue.queue_count();
+            match output {
+                Some(path) => path::write_solutions(&solutions, total, format, BufWriter::new(File::create(path)?)),
+                None => path::write_solutions(&solutions, total, format, std::io::stdout().lock()),
+            }
+        }
+        Command::Cover { queue, solutions, fumen, no_hold, output, format } => {
+            let queue = queue::CombinatoricQueue::from_str(&queue).expect("valid queue");
+            let solutions = load_solutions(data_dir, &solutions, &fumen, &queue, !no_hold);
 
-        use fumen::{CellColor, Fumen, Page};
+            let matrix = cover::CoverMatrix::compute(solutions, &queue, !no_hold);
+            match output {
+                Some(path) => matrix.write(format, BufWriter::new(File::create(path)?)),
+                None => matrix.write(format, std::io::stdout().lock()),
+            }
+        }
+        Command::Minimal { queue, solutions, fumen, no_hold, best, budget, output, format } => {
+            let queue = queue::CombinatoricQueue::from_str(&queue).expect("valid queue");
+            let solutions = load_solutions(data_dir, &solutions, &fumen, &queue, !no_hold);
+            let matrix = cover::CoverMatrix::compute(solutions, &queue, !no_hold);
 
-        let fumen = Fumen::decode(encoded).ok()?;
-        let page: &Page = fumen.pages.get(0)?;
+            let selection = match best {
+                Some(k) => minimal::best_k(&matrix, k, budget),
+                None => minimal::minimum_cover(&matrix, budget),
+            };
+            match output {
+                Some(path) => selection.write(&matrix, best.is_some(), format, BufWriter::new(File::create(path)?)),
+                None => selection.write(&matrix, best.is_some(), format, std::io::stdout().lock()),
+            }
+        }
+        Command::Stats { top } => {
+            let giga = load(data_dir);
+            stats::GraphStats::compute(&giga).print(top);
+            Ok(())
+        }
+        Command::Export { output, format, fumen } => {
+            let giga = load(data_dir);
+            let root = fumen.map(|fumen| Board(fumens::decode_fumen(&fumen).expect("valid fumen")));
+            let file = BufWriter::new(File::create(&output)?);
+            stats::export(&giga, root, format, file)?;
+            println!("exported graph to {output}");
+            Ok(())
+        }
+        Command::Verify { sample, seed, top } => {
+            // missing or truncated shards are a failure of the data, not of the command
+            let giga = match legal_boards::read_gigapan(data_dir) {
+                Ok(giga) => mirror_expand(giga.freeze()),
+                Err(error) => {
+                    println!("unable to read the gigapan shards in {data_dir}: {error}");
+                    std::process::exit(1);
+                }
+            };
+            let report = verify::verify(&giga, sample, seed);
+            report.print(top);
+            if !report.is_ok() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::FullSeeTable { output, fumen, no_hold, two_line, max_memory } => {
+            let boards: Vec<Board> = fumen
+                .iter()
+                .map(|fumen| Board(fumens::decode_fumen(fumen).expect("valid fumen")))
+                .collect();
+            let giga = load_for(data_dir, &boards);
 
-        if page.field[4..] != [[CellColor::Empty; 10]; 19]
-            || page.garbage_row != [CellColor::Empty; 10]
-        {
-            return None;
+            let instant = std::time::Instant::now();
+            let table = match full_see::FullSeeTable::build(&giga, &boards, !no_hold, two_line, max_memory << 20) {
+                Ok(table) => table,
+                Err(error) => {
+                    eprintln!("{error}");
+                    return Ok(());
+                }
+            };
+            table.write(BufWriter::new(File::create(&output)?))?;
+            eprintln!("wrote counts for {} boards to {output} in {:.3}s", table.len(), instant.elapsed().as_secs_f64());
+            Ok(())
+        }
+        Command::FullSee { table, fumen, blank_start } => {
+            let board = Board(fumens::decode_fumen(&fumen).expect("valid fumen"));
+            let table = full_see::FullSeeTable::read(std::io::BufReader::new(File::open(&table)?))?;
+            let pieces = (40 - board.0.count_ones() as usize) / 4 + 1;
+            println!("use_hold: {} two_line: {}", table.use_hold, table.two_line);
+            for left in (1..=7).rev() {
+                let (passing, total) = match table.phase(board, left, !blank_start) {
+                    Some(counts) => counts,
+                    None => {
+                        eprintln!("{board} isn't in the table");
+                        return Ok(());
+                    }
+                };
+                let chance = passing as f64 / total as f64 * 100.0;
+                println!("{}: {passing}/{total} {chance:.2}%", full_see::phase_pattern(pieces, left));
+            }
+            Ok(())
         }
+    }
+}
+
+fn parse_seconds(seconds: &str) -> Result<std::time::Duration, String> {
+    seconds
+        .parse::<f64>()
+        .ok()
+        .and_then(|seconds| std::time::Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("{seconds} is not a number of seconds"))
+}
+
+fn parse_previews(previews: &str) -> Result<std::ops::RangeInclusive<usize>, String> {
+    let parse = |count: &str| count.trim().parse::<usize>().map_err(|_| format!("{previews} is not a preview count or range like 1-7"));
+    let (start, end) = match previews.split_once('-') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(previews)?, parse(previews)?),
+    };
+    if start > end {
+        return Err(format!("{previews} is an empty range"));
+    }
+    Ok(start..=end)
+}
+
+/// Decode the solution fumens, or enumerate every solution from the fumen
+/// board if there are none.
+fn load_solutions(data_dir: &str, solutions: &[String], fumen: &str, queue: &queue::CombinatoricQueue, use_hold: bool) -> Vec<BrokenBoard> {
+    if !solutions.is_empty() {
+        return solutions.iter().flat_map(|fumen| fumens::decode_solutions(fumen).expect("valid solution fumen")).collect();
+    }
+    let board = Board(fumens::decode_fumen(fumen).expect("valid fumen"));
+    let giga = load_for(data_dir, &[board]);
+    if giga.edges(board).is_none() {
+        eprintln!("no perfect clear is possible from {board}");
+        return Vec::new();
+    }
+    path::find_solutions(&giga, board, queue, use_hold).into_iter().map(|solution| solution.board).collect()
+}
 
-        let mut field = 0;
-        for idx in 0..40 {
-            let cell: CellColor = page.field[idx / 10][idx % 10];
-            let filled = cell != CellColor::Empty;
-            field |= (filled as u64) << idx;
+/// Read the whole graph, expanding mirror reduced shards.
+fn load(data_dir: &str) -> FrozenGigapan {
+    let giga = legal_boards::read_gigapan(data_dir)
+        .unwrap_or_else(|_| panic!("unable to find gigapan shards in {data_dir}! try `gigapan generate`"))
+        .freeze();
+    eprintln!("giga loaded: {}",giga.len());
+    mirror_expand(giga)
+}
+
+/// Read the graph if there is one, and extend it with the boards reachable
+/// from `boards` if they're missing.  Logs go to stderr, so the results can be
+/// piped.
+fn load_for(data_dir: &str, boards: &[Board]) -> FrozenGigapan {
+    let mut giga = match legal_boards::read_gigapan(data_dir) {
+        Ok(giga) => giga.freeze(),
+        Err(_) => {
+            eprintln!("unable to find gigapan shards in {data_dir}, building a graph for these boards only");
+            legal_boards::boardgraph::Gigapan::new().freeze()
         }
+    };
 
-        Some(field)
+    eprintln!("giga loaded: {}",giga.len());
 
-}
\ No newline at end of file
+    let missing: Vec<Board> = boards.iter().copied().filter(|&board| giga.edges(board).is_none()).collect();
+    if !missing.is_empty() {
+        eprintln!("board not found in giga, building subgraph...");
+        let subgraph = legal_boards::boardgraph::subgraph(&missing);
+        giga = legal_boards::boardgraph::merge(giga, subgraph);
+        eprintln!("giga extended: {}",giga.len());
+    }
+    giga
+}
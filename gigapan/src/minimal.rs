@@ -0,0 +1,356 @@
+//! The fewest solutions which together cover every solvable queue, or the k
+//! solutions which cover the most queues.
+//!
+//! Both are searched exactly with branch and bound, starting from the greedy
+//! answer.  When the search runs out of its node budget, the greedy answer is
+//! kept along with a bound on how far from optimal it may be.
+
+use std::io::Write;
+
+use crate::calculate::OutputFormat;
+use crate::cover::CoverMatrix;
+use crate::fumens::encode_broken_boards;
+
+/// A choice of solutions, by index into the matrix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub solutions: Vec<usize>,
+    /// Queues covered by at least one chosen solution
+    pub covered: usize,
+    /// Whether the search finished, so the selection is optimal
+    pub optimal: bool,
+    /// For a minimum cover, the fewest solutions any cover could use.  For the
+    /// best k, the most queues any k solutions could cover.
+    pub bound: usize,
+}
+
+impl Selection {
+    /// Write the chosen solutions with how many queues each covers.  `best`
+    /// says whether this is the best k rather than a minimum cover, which
+    /// changes what the bound means.
+    pub fn write(&self, matrix: &CoverMatrix, best: bool, format: OutputFormat, mut to: impl Write) -> std::io::Result<()> {
+        let total = matrix.queues.len();
+        let counts = matrix.solution_counts();
+        let chosen = self.solutions.iter().map(|&solution| (&matrix.solutions[solution], counts[solution]));
+
+        match format {
+            OutputFormat::Text => {
+                let pages = chosen.map(|(solution, count)| (solution, Some(format!("{count}/{total}"))));
+                writeln!(to, "{}", encode_broken_boards(pages))?;
+                writeln!(to, "{} solutions cover {}/{total} queues ({:.2}%)", self.solutions.len(), self.covered, self.covered as f64 / total as f64 * 100.0)?;
+                match (self.optimal, best) {
+                    (true, _) => writeln!(to, "optimal")?,
+                    (false, false) => writeln!(to, "not proven optimal, any cover needs at least {} solutions", self.bound)?,
+                    (false, true) => writeln!(to, "not proven optimal, at most {} queues can be covered", self.bound)?,
+                }
+            }
+            OutputFormat::Json => {
+                let solutions: Vec<serde_json::Value> = chosen
+                    .map(|(solution, count)| serde_json::json!({
+                        "fumen": encode_broken_boards([(solution, None)]),
+                        "covered": count,
+                    }))
+                    .collect();
+                serde_json::to_writer(&mut to, &serde_json::json!({
+                    "solutions": solutions,
+                    "covered": self.covered,
+                    "total": total,
+                    "optimal": self.optimal,
+                    "bound": self.bound,
+                }))?;
+                writeln!(to)?;
+            }
+            OutputFormat::Csv => {
+                writeln!(to, "# covered: {}", self.covered)?;
+                writeln!(to, "# total: {total}")?;
+                writeln!(to, "# optimal: {}", self.optimal)?;
+                writeln!(to, "# bound: {}", self.bound)?;
+                writeln!(to, "solution,covered")?;
+                for (solution, count) in chosen {
+                    writeln!(to, "{},{count}", encode_broken_boards([(solution, None)]))?;
+                }
+            }
+        }
+        to.flush()
+    }
+}
+
+/// The queues each solution covers, as bit sets
+struct Sets {
+    sets: Vec<Vec<u64>>,
+    words: usize,
+    /// The solutions covering each queue
+    covering: Vec<Vec<usize>>,
+}
+
+impl Sets {
+    fn new(matrix: &CoverMatrix) -> Self {
+        let words = matrix.queues.len().div_ceil(64);
+        let mut sets = vec![vec![0; words]; matrix.solutions.len()];
+        let mut covering = vec![Vec::new(); matrix.queues.len()];
+        for (queue, row) in matrix.covered.iter().enumerate() {
+            for (solution, &covered) in row.iter().enumerate() {
+                if covered {
+                    sets[solution][queue / 64] |= 1 << (queue % 64);
+                    covering[queue].push(solution);
+                }
+            }
+        }
+        Sets { sets, words, covering }
+    }
+
+    fn union(&self, chosen: &[usize]) -> Vec<u64> {
+        let mut union = vec![0; self.words];
+        for &solution in chosen {
+            or_into(&mut union, &self.sets[solution]);
+        }
+        union
+    }
+
+    /// How many queues `solution` covers which aren't in `covered`
+    fn gain(&self, solution: usize, covered: &[u64]) -> usize {
+        self.sets[solution]
+            .iter()
+            .zip(covered)
+            .map(|(set, covered)| (set & !covered).count_ones() as usize)
+            .sum()
+    }
+
+    /// Pick the solution with the largest gain until `done` says to stop
+    fn greedy(&self, mut done: impl FnMut(&[usize], usize) -> bool) -> Vec<usize> {
+        let mut chosen = Vec::new();
+        let mut covered = vec![0; self.words];
+        loop {
+            let count = count(&covered);
+            if done(&chosen, count) {
+                return chosen;
+            }
+            let best = (0..self.sets.len())
+                .map(|solution| (self.gain(solution, &covered), solution))
+                .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+            match best {
+                Some((gain, solution)) if gain > 0 => {
+                    chosen.push(solution);
+                    or_into(&mut covered, &self.sets[solution]);
+                }
+                _ => return chosen,
+            }
+        }
+    }
+}
+
+fn or_into(into: &mut [u64], set: &[u64]) {
+    for (into, set) in into.iter_mut().zip(set) {
+        *into |= set;
+    }
+}
+
+fn count(set: &[u64]) -> usize {
+    set.iter().map(|word| word.count_ones() as usize).sum()
+}
+
+/// The fewest solutions covering every queue which any solution covers
+pub fn minimum_cover(matrix: &CoverMatrix, budget: usize) -> Selection {
+    let sets = Sets::new(matrix);
+    let target = sets.union(&(0..sets.sets.len()).collect::<Vec<_>>());
+    let target_count = count(&target);
+    let largest = sets.sets.iter().map(|set| count(set)).max().unwrap_or(0);
+    let lower_bound = |uncovered: usize| match largest {
+        0 => 0,
+        largest => uncovered.div_ceil(largest),
+    };
+
+    let greedy = sets.greedy(|_, covered| covered == target_count);
+    let mut search = CoverSearch {
+        sets: &sets,
+        best: greedy,
+        nodes: 0,
+        budget,
+        largest,
+    };
+    let mut chosen = Vec::new();
+    let finished = search.search(&mut chosen, &vec![0; sets.words], target_count);
+
+    let best = search.best;
+    Selection {
+        covered: count(&sets.union(&best)),
+        optimal: finished,
+        bound: if finished { best.len() } else { lower_bound(target_count) },
+        solutions: best,
+    }
+}
+
+struct CoverSearch<'a> {
+    sets: &'a Sets,
+    best: Vec<usize>,
+    nodes: usize,
+    budget: usize,
+    largest: usize,
+}
+
+impl CoverSearch<'_> {
+    /// Returns false if the budget ran out
+    fn search(&mut self, chosen: &mut Vec<usize>, covered: &[u64], target_count: usize) -> bool {
+        self.nodes += 1;
+        if self.nodes > self.budget {
+            return false;
+        }
+
+        let uncovered = target_count - count(covered);
+        if uncovered == 0 {
+            if chosen.len() < self.best.len() {
+                self.best = chosen.clone();
+            }
+            return true;
+        }
+        let needed = uncovered.div_ceil(self.largest);
+        if chosen.len() + needed >= self.best.len() {
+            return true;
+        }
+
+        // Some chosen solution must cover the uncovered queue which the
+        // fewest solutions cover, so branch on those.
+        let mut branches = self
+            .sets
+            .covering
+            .iter()
+            .enumerate()
+            .filter(|&(queue, covering)| !covering.is_empty() && covered[queue / 64] & (1 << (queue % 64)) == 0)
+            .map(|(_, covering)| covering)
+            .min_by_key(|covering| covering.len())
+            .unwrap()
+            .clone();
+        branches.sort_by_key(|&solution| std::cmp::Reverse(self.sets.gain(solution, covered)));
+        for solution in branches {
+            let mut next = covered.to_vec();
+            or_into(&mut next, &self.sets.sets[solution]);
+            chosen.push(solution);
+            let finished = self.search(chosen, &next, target_count);
+            chosen.pop();
+            if !finished {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The `k` solutions which together cover the most queues
+pub fn best_k(matrix: &CoverMatrix, k: usize, budget: usize) -> Selection {
+    let sets = Sets::new(matrix);
+    let greedy = sets.greedy(|chosen, _| chosen.len() == k);
+    let mut search = BestKSearch {
+        sets: &sets,
+        k,
+        best_count: count(&sets.union(&greedy)),
+        best: greedy,
+        nodes: 0,
+        budget,
+    };
+    let all = count(&sets.union(&(0..sets.sets.len()).collect::<Vec<_>>()));
+    let root_bound = search.upper_bound(&vec![0; sets.words], 0, k).min(all);
+    let finished = search.search(&mut Vec::new(), &vec![0; sets.words], 0);
+
+    Selection {
+        covered: search.best_count,
+        optimal: finished,
+        bound: if finished { search.best_count } else { root_bound },
+        solutions: search.best,
+    }
+}
+
+struct BestKSearch<'a> {
+    sets: &'a Sets,
+    k: usize,
+    best: Vec<usize>,
+    best_count: usize,
+    nodes: usize,
+    budget: usize,
+}
+
+impl BestKSearch<'_> {
+    /// Coverage is submodular, so adding the `left` largest gains from the
+    /// solutions from `from` on can't be beaten.
+    fn upper_bound(&self, covered: &[u64], from: usize, left: usize) -> usize {
+        let mut gains: Vec<usize> = (from..self.sets.sets.len())
+            .map(|solution| self.sets.gain(solution, covered))
+            .collect();
+        gains.sort_unstable_by(|a, b| b.cmp(a));
+        count(covered) + gains.iter().take(left).sum::<usize>()
+    }
+
+    /// Choose solutions in increasing order, so each set is tried once.
+    /// Returns false if the budget ran out.
+    fn search(&mut self, chosen: &mut Vec<usize>, covered: &[u64], from: usize) -> bool {
+        self.nodes += 1;
+        if self.nodes > self.budget {
+            return false;
+        }
+
+        let covered_count = count(covered);
+        if covered_count > self.best_count {
+            self.best_count = covered_count;
+            self.best = chosen.clone();
+        }
+        let left = self.k - chosen.len();
+        if left == 0 || self.upper_bound(covered, from, left) <= self.best_count {
+            return true;
+        }
+
+        for solution in from..self.sets.sets.len() {
+            if self.sets.gain(solution, covered) == 0 {
+                continue;
+            }
+            let mut next = covered.to_vec();
+            or_into(&mut next, &self.sets.sets[solution]);
+            chosen.push(solution);
+            let finished = self.search(chosen, &next, solution + 1);
+            chosen.pop();
+            if !finished {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+fn matrix(rows: &[&[bool]]) -> CoverMatrix {
+    let solutions = rows.first().map_or(0, |row| row.len());
+    CoverMatrix {
+        solutions: vec![srs_4l::brokenboard::BrokenBoard::empty(); solutions],
+        queues: vec![Default::default(); rows.len()],
+        covered: rows.iter().map(|row| row.to_vec()).collect(),
+    }
+}
+
+#[test]
+fn beats_greedy() {
+    // Greedy takes the middle solution first and then needs both others,
+    // while the outer two cover everything.
+    let (o, x) = (true, false);
+    let matrix = matrix(&[
+        &[o, o, x],
+        &[o, o, x],
+        &[o, o, x],
+        &[o, x, x],
+        &[x, o, o],
+        &[x, o, o],
+        &[x, o, o],
+        &[x, x, o],
+    ]);
+
+    let cover = minimum_cover(&matrix, usize::MAX);
+    assert!(cover.optimal);
+    assert_eq!(cover.covered, 8);
+    assert_eq!(cover.solutions.len(), 2);
+    assert!(!cover.solutions.contains(&1));
+
+    let best = best_k(&matrix, 1, usize::MAX);
+    assert_eq!((best.solutions, best.covered, best.optimal), (vec![1], 6, true));
+
+    // Out of budget, the greedy answer is kept with a bound.
+    let cover = minimum_cover(&matrix, 1);
+    assert!(!cover.optimal);
+    assert_eq!((cover.solutions.len(), cover.bound), (3, 2));
+}
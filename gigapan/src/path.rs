@@ -0,0 +1,307 @@
+//! Every perfect clear solution for a board and pattern, found natively on the
+//! gigapan graph, along with how many queues of the pattern each one covers.
+
+use std::io::Write;
+
+use compute::ShardedHashMap;
+use hashbrown::HashMap;
+use legal_boards::boardgraph::{FrozenGigapan, GigapanLookup};
+use rayon::prelude::{IntoParallelRefMutIterator, IntoParallelIterator, ParallelIterator};
+use srs_4l::brokenboard::BrokenBoard;
+use srs_4l::gameplay::{Board, Shape};
+use srs_4l::vector::Placements;
+
+use crate::fumens::encode_broken_boards;
+use crate::queue::{Bag, CombinatoricQueue, QueueState};
+
+type PlaceStage = ShardedHashMap<BrokenBoard, Vec<QueueState>, 16>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PathFormat {
+    Csv,
+    Fumen,
+}
+
+/// A solution and how many queues of the pattern can build it
+pub struct Solution {
+    pub board: BrokenBoard,
+    pub covered: usize,
+}
+
+/// Find every distinct solution from `start` which some queue of the pattern
+/// can build, most covering first.  Like
+/// [`limited_see_chance`](crate::calculate::limited_see_chance), the pattern
+/// has one more piece than the solutions, which may be left over.
+pub fn find_solutions(
+    gigapan: &FrozenGigapan,
+    start: Board,
+    combinatoric_queue: &CombinatoricQueue,
+    use_hold: bool,
+) -> Vec<Solution> {
+    let counted_bags = combinatoric_queue.get_counted_bags();
+    let start = BrokenBoard::from_garbage(start.0);
+
+    let mut prev: PlaceStage = ShardedHashMap::new();
+    prev.insert(start.clone(), counted_bags.first().unwrap().1.init_hold());
+
+    for (i, bag) in counted_bags.iter().skip(1) {
+        let next: PlaceStage = ShardedHashMap::new();
+
+        prev.par_iter_mut().for_each(|(old_board, old_queues)| {
+            let edges = match gigapan.edges(old_board.board) {
+                Some(edges) => edges,
+                None => return,
+            };
+            for shape in Shape::ALL {
+                let new_queues = bag.take(old_queues, shape, i == &0, use_hold);
+                if new_queues.is_empty() {
+                    continue;
+                }
+
+                for (piece, new_board) in Placements::place(old_board.board, shape).canonical() {
+                    if !edges.contains(shape, new_board) {
+                        continue;
+                    }
+                    let new_board = old_board.place(piece);
+                    let mut lock = next.get_shard_guard(&new_board);
+                    let queues = lock.entry(new_board).or_default();
+                    for &queue in &new_queues {
+                        if !queues.contains(&queue) {
+                            queues.push(queue);
+                        }
+                    }
+                }
+            }
+        });
+        prev = next;
+    }
+
+    let boards: Vec<BrokenBoard> = prev
+        .into_iter()
+        .map(|(board, _queues)| board)
+        .filter(|board| board.board == Board::full())
+        .collect();
+
+    let mut solutions: Vec<Solution> = boards
+        .into_par_iter()
+        .map(|board| {
+            let covered = Cover::new(&start, &board, use_hold).count(&counted_bags);
+            Solution { board, covered }
+        })
+        .collect();
+    solutions.sort_unstable_by(|a, b| b.covered.cmp(&a.covered).then_with(|| a.board.cmp(&b.board)));
+    solutions
+}
+
+/// Write the solutions as csv, or as one fumen with a page per solution
+pub fn write_solutions(
+    solutions: &[Solution],
+    total: usize,
+    format: PathFormat,
+    mut to: impl Write,
+) -> std::io::Result<()> {
+    let percent = |covered: usize| covered as f64 / total as f64 * 100.0;
+    match format {
+        PathFormat::Csv => {
+            writeln!(to, "fumen,covered,total,chance")?;
+            for solution in solutions {
+                let fumen = encode_broken_boards([(&solution.board, None)]);
+                writeln!(to, "{fumen},{},{total},{}", solution.covered, percent(solution.covered))?;
+            }
+        }
+        PathFormat::Fumen => {
+            let pages = solutions.iter().map(|solution| {
+                let comment = format!("{}/{total} ({:.2}%)", solution.covered, percent(solution.covered));
+                (&solution.board, Some(comment))
+            });
+            writeln!(to, "{}", encode_broken_boards(pages))?;
+        }
+    }
+    to.flush()
+}
+
+/// Where a queue can be while building one solution: which of its pieces are
+/// placed, as a bit set, and what is in hold
+type Config = (u16, Option<Shape>);
+
+/// Decides which queues can build a solution, piece by piece
+pub struct Cover<'a> {
+    solution: &'a BrokenBoard,
+    use_hold: bool,
+    /// Partially built solutions, by which pieces are placed
+    boards: HashMap<u16, BrokenBoard>,
+    /// Which pieces can be placed next, by which are already placed
+    moves: HashMap<(u16, Shape), Vec<u16>>,
+    counts: HashMap<(usize, QueueState, Vec<Config>), usize>,
+}
+
+impl<'a> Cover<'a> {
+    pub fn new(start: &BrokenBoard, solution: &'a BrokenBoard, use_hold: bool) -> Self {
+        let mut boards = HashMap::new();
+        boards.insert(0, start.clone());
+        Cover {
+            solution,
+            use_hold,
+            boards,
+            moves: HashMap::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    fn done(&self) -> u16 {
+        (1 << self.solution.pieces.len()) - 1
+    }
+
+    /// Placed piece sets reachable by placing `shape` with SRS
+    fn place(&mut self, placed: u16, shape: Shape) -> &[u16] {
+        if !self.moves.contains_key(&(placed, shape)) {
+            let board = self.boards[&placed].clone();
+            let reachable: Vec<_> = Placements::place(board.board, shape)
+                .canonical()
+                .map(|(piece, _)| piece)
+                .collect();
+
+            let mut next = Vec::new();
+            for (index, &piece) in self.solution.pieces.iter().enumerate() {
+                if piece.shape != shape || placed & (1 << index) != 0 {
+                    continue;
+                }
+                if let Some(piece) = board.placeable(piece) {
+                    if reachable.contains(&piece) {
+                        let new = placed | (1 << index);
+                        self.boards.entry(new).or_insert_with(|| board.place(piece));
+                        next.push(new);
+                    }
+                }
+            }
+            self.moves.insert((placed, shape), next);
+        }
+        &self.moves[&(placed, shape)]
+    }
+
+    /// The configs after `shape` comes out of the queue
+    fn step(&mut self, configs: &[Config], shape: Shape) -> Vec<Config> {
+        let done = self.done();
+        let mut next = Vec::new();
+        let mut push = |config: Config| {
+            if !next.contains(&config) {
+                next.push(config);
+            }
+        };
+
+        for &(placed, hold) in configs {
+            if placed == done {
+                push((placed, None));
+                continue;
+            }
+            for &new in self.place(placed, shape).to_vec().iter() {
+                push((new, hold));
+            }
+            if !self.use_hold {
+                continue;
+            }
+            match hold {
+                None => push((placed, Some(shape))),
+                Some(hold) if hold != shape => {
+                    for &new in self.place(placed, hold).to_vec().iter() {
+                        push((new, Some(shape)));
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        next.sort_unstable();
+        next
+    }
+
+    /// Whether this set queue can build the solution
+    pub fn covers(&mut self, queue: impl IntoIterator<Item = Shape>) -> bool {
+        let mut configs = vec![(0, None)];
+        for shape in queue {
+            configs = self.step(&configs, shape);
+            if configs.is_empty() {
+                return false;
+            }
+        }
+        let done = self.done();
+        configs.iter().any(|&(placed, _)| placed == done)
+    }
+
+    /// How many queues of the pattern can build the solution
+    pub fn count(&mut self, counted_bags: &[(u8, Bag)]) -> usize {
+        let state = QueueState(counted_bags.first().unwrap().1.full);
+        self.count_from(counted_bags, 0, state, vec![(0, None)])
+    }
+
+    fn count_from(
+        &mut self,
+        counted_bags: &[(u8, Bag)],
+        revealed_pieces: usize,
+        queue_state: QueueState,
+        configs: Vec<Config>,
+    ) -> usize {
+        if configs.is_empty() {
+            return 0;
+        }
+        if revealed_pieces >= counted_bags.len() {
+            let done = self.done();
+            return configs.iter().any(|&(placed, _)| placed == done) as usize;
+        }
+        let key = (revealed_pieces, queue_state, configs);
+        if let Some(&count) = self.counts.get(&key) {
+            return count;
+        }
+        let (_, queue_state, configs) = &key;
+
+        let (bag_placement, bag) = &counted_bags[revealed_pieces];
+        let queue_state = if bag_placement == &0 { queue_state.next(bag) } else { *queue_state };
+
+        let mut count = 0;
+        for shape in Shape::ALL {
+            if let Some(next_state) = queue_state.take(bag, shape) {
+                let next_configs = self.step(configs, shape);
+                count += self.count_from(counted_bags, revealed_pieces + 1, next_state, next_configs);
+            }
+        }
+
+        self.counts.insert(key, count);
+        count
+    }
+}
+
+#[test]
+fn two_lines() {
+    use std::str::FromStr;
+
+    let (board, gigapan) = crate::fixture::small_board();
+
+    let queue = CombinatoricQueue::from_str("OOOOOOO").unwrap();
+    let solutions = find_solutions(&gigapan, board, &queue, true);
+    assert_eq!(solutions.len(), 1);
+    assert_eq!(solutions[0].covered, 1);
+
+    // Each solution's coverage must agree with the queues which can build it
+    // without hold, extended by a leftover piece and then unheld.
+    let queue = CombinatoricQueue::from_str("[OOOOIIT]p7").unwrap();
+    let queues: hashbrown::HashSet<srs_4l::queue::Queue> =
+        crate::queue::get_queue_permutations(&queue.get_counted_bags(), None, None)
+            .into_iter()
+            .map(|queue| queue.into_iter().collect())
+            .collect();
+    let solutions = find_solutions(&gigapan, board, &queue, true);
+    assert!(solutions.len() > 1);
+    for solution in &solutions {
+        let supporting: Vec<srs_4l::queue::Queue> = solution
+            .board
+            .supporting_queues()
+            .into_iter()
+            .flat_map(|queue| Shape::ALL.into_iter().map(move |shape| queue.push_last(shape)))
+            .collect();
+        let covered = srs_4l::queue::Queue::unhold_many(&supporting)
+            .into_iter()
+            .filter(|queue| queues.contains(queue))
+            .count();
+        assert_eq!(solution.covered, covered);
+        assert!(covered > 0);
+    }
+}
@@ -4,7 +4,7 @@ use std::{
 };
 
 use hashbrown::HashMap;
-use srs_4l::gameplay::Shape;
+use srs_4l::{feasibility::ShapeCounts, gameplay::Shape};
 use std::collections::VecDeque;
 
 
@@ -33,13 +33,24 @@ fn shape_set_from_list(list: &[Shape])->HashMap<Shape, usize>{
 
 pub struct CombinatoricQueue {
     bags: Vec<BagInput>,
+    hold: Option<Shape>,
 }
 impl CombinatoricQueue {
     pub fn new() -> Self {
         Self {
             bags: Vec::new(),
+            hold: None,
         }
     }
+    /// The piece known to be in hold at the start, if any
+    pub fn hold(&self) -> Option<Shape>{
+        self.hold
+    }
+    /// Start with `shape` in hold.  It comes first in the counted bags, so it's
+    /// taken into hold wherever the first piece of the queue would be
+    pub fn set_hold(&mut self, shape: Shape){
+        self.hold = Some(shape);
+    }
     pub fn queue_count(&self)->usize{
         let mut count = 1;
         for bag_input in &self.bags{
@@ -51,8 +62,60 @@ impl CombinatoricQueue {
         }
         count
     }
+    /// Position of a full queue of this pattern in the order of
+    /// [`get_queue_permutations`], or `None` if the pattern can't produce it
+    pub fn rank(&self, queue: &[Shape]) -> Option<usize>{
+        if queue.len() != self.get_counted_bags().len(){
+            return None;
+        }
+        self.prefix_rank(queue)
+    }
+    /// [`rank`](Self::rank) of the first full queue which starts with `prefix`,
+    /// or `None` if the pattern can't start with it
+    pub fn prefix_rank(&self, prefix: &[Shape]) -> Option<usize>{
+        let counted_bags = self.get_counted_bags();
+        if prefix.len() > counted_bags.len(){
+            return None;
+        }
+        let mut rank = 0;
+        let mut state = QueueState(counted_bags.first()?.1.full);
+        for (i, ((bag_placement, bag), &shape)) in counted_bags.iter().zip(prefix).enumerate(){
+            if bag_placement == &0{state = state.next(bag);}
+            for before in Shape::ALL.into_iter().take_while(|&before| before != shape){
+                if let Some(state) = state.take(bag, before){
+                    rank += count_queues(&counted_bags, state, i+1, counted_bags.len());
+                }
+            }
+            state = state.take(bag, shape)?;
+        }
+        Some(rank)
+    }
+    /// The full queue at position `index` in the order of
+    /// [`get_queue_permutations`], the inverse of [`rank`](Self::rank)
+    pub fn unrank(&self, mut index: usize) -> Option<VecDeque<Shape>>{
+        let counted_bags = self.get_counted_bags();
+        let mut queue = VecDeque::with_capacity(counted_bags.len());
+        let mut state = QueueState(counted_bags.first()?.1.full);
+        for (i, (bag_placement, bag)) in counted_bags.iter().enumerate(){
+            if bag_placement == &0{state = state.next(bag);}
+            let (shape, next) = Shape::ALL.into_iter().filter_map(|shape|{
+                let next = state.take(bag, shape)?;
+                let count = count_queues(&counted_bags, next, i+1, counted_bags.len());
+                if index < count{
+                    Some((shape, next))
+                }else{
+                    index -= count;
+                    None
+                }
+            }).next()?;
+            queue.push_back(shape);
+            state = next;
+        }
+        Some(queue)
+    }
     pub fn get_counted_bags(&self)-> Vec<(u8, Bag)>{
-        self.bags.iter().map(|input|{//cursed boxed iterators in order to have Once and Map in parralel 
+        let hold = self.hold.map(|shape| (0, Bag::new(&[shape], 1)));
+        hold.into_iter().chain(self.bags.iter().map(|input|{//cursed boxed iterators in order to have Once and Map in parralel 
             let iter: Box<dyn Iterator<Item= (u8, Bag)>> = if input.ordered{
                 Box::new(input.shapes.iter().map(|&shape|{
                     (0, Bag::new(&[shape], 1))
@@ -68,7 +131,7 @@ impl CombinatoricQueue {
                 )
             };
             iter
-        }).flatten().collect()
+        }).flatten()).collect()
     }
     pub fn add_shapes(&mut self, shapes: Vec<Shape>) {
         let count = shapes.len() as u8;
@@ -113,6 +176,45 @@ pub fn get_queue_permutations(counted_bags: &[(u8, Bag)], start_state: Option<(u
     permutations
 }
 
+/// Upper bounds on how many of each shape are still to be revealed, when the
+/// first `revealed` of `counted_bags` have been and `queue_state` is the state
+/// after them
+pub fn unrevealed_shapes(counted_bags: &[(u8, Bag)], queue_state: QueueState, revealed: usize) -> ShapeCounts{
+    let mut counts = ShapeCounts::default();
+    let mut state = queue_state;
+    let mut i = revealed;
+    while i < counted_bags.len(){
+        let (bag_placement, bag) = &counted_bags[i];
+        if bag_placement == &0{state = state.next(bag);}
+        let draws = 1 + counted_bags[i+1..].iter().take_while(|(bag_placement, _)| bag_placement != &0).count();
+        for shape in Shape::ALL{
+            let left = (state.0 & bag.masks[shape as usize]).count_ones() as usize;
+            counts.add(shape, left.min(draws) as u8);
+        }
+        i += draws;
+    }
+    counts
+}
+
+/// Number of ways the queue can continue from piece `position` up to piece
+/// `depth`, when `queue_state` is the state after the pieces before it.  Each
+/// bag is counted as a whole instead of enumerating its draws.
+pub fn count_queues(counted_bags: &[(u8, Bag)], queue_state: QueueState, position: usize, depth: usize) -> usize{
+    let depth = depth.min(counted_bags.len());
+    let mut count = 1;
+    let mut state = queue_state;
+    let mut i = position;
+    while i < depth{
+        let (bag_placement, bag) = &counted_bags[i];
+        if bag_placement == &0{state = state.next(bag);}
+        let draws = 1 + counted_bags[i+1..].iter().take_while(|(bag_placement, _)| bag_placement != &0).count();
+        let draws = draws.min(depth - i);
+        count *= state.count_draws(bag, draws);
+        i += draws;
+    }
+    count
+}
+
 fn recursive_permute_bags(bags: &[(u8, Bag)], permutations: &mut Vec<VecDeque<Shape>>, depth: usize, max_depth:usize, state: QueueState, queue: &mut VecDeque<Shape>){
     if depth >= max_depth{
         permutations.push(queue.clone());
@@ -152,8 +254,25 @@ fn queuecombo(){
 
 }
 
+#[test]
+fn unrevealed(){
+    let queue = CombinatoricQueue::from_str("[IJSZ]!IJ*p3").unwrap();
+    let counted_bags = queue.get_counted_bags();
+    let start = QueueState(counted_bags.first().unwrap().1.full);
+
+    let counts = unrevealed_shapes(&counted_bags, start, 0);
+    assert_eq!(counts.0, [3, 3, 1, 1, 2, 1, 2]);
+
+    let state = start.next(&counted_bags[0].1).take(&counted_bags[0].1, Shape::S).unwrap();
+    let counts = unrevealed_shapes(&counted_bags, state, 1);
+    assert_eq!(counts.0, [3, 3, 1, 1, 1, 1, 2]);
+}
+
 impl Display for CombinatoricQueue{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(hold) = self.hold{
+            f.write_fmt(format_args!("({:?})", hold))?;
+        }
         let mut iter = self.bags.iter().peekable();
         while let Some(inputs) = iter.next(){
             let star = inputs.shapes == &Shape::ALL;
@@ -193,6 +312,21 @@ fn queuegen_string(){
     assert_eq!(queue.to_string(), "*p3,*,*p7,*,[IOSZ]p2,JLT,[SZ]!");
 }
 
+#[test]
+fn starting_hold(){
+    let queue = CombinatoricQueue::from_str("(T)*p7").unwrap();
+    assert_eq!(queue.hold(), Some(Shape::T));
+    assert_eq!(queue.to_string(), "(T)*p7");
+    assert_eq!(queue.queue_count(), 5040);
+
+    let counted_bags = queue.get_counted_bags();
+    assert_eq!(counted_bags.len(), 8);
+    assert_eq!(counted_bags[0].1.init_hold().iter().map(|state| state.hold()).collect::<Vec<_>>(), vec![Some(Shape::T)]);
+
+    assert!(CombinatoricQueue::from_str("(T*p7").is_err());
+    assert!(CombinatoricQueue::from_str("*p7(T)").is_err());
+}
+
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Bag {
     pub count: u8,
@@ -314,13 +448,43 @@ impl QueueState {
         Some(new)
     }
 
+    /// How many ways `draws` more pieces can come out of `bag`, as ordered
+    /// selections from the multiset of shapes left in it
+    pub fn count_draws(self, bag: &Bag, draws: usize) -> usize {
+        // ways[n] is the number of orderings of n pieces using the shapes so
+        // far, and k more of a shape can go into any k of the n+k positions
+        let mut ways = vec![0; draws + 1];
+        ways[0] = 1;
+        for mask in bag.masks {
+            let left = (self.0 & mask).count_ones() as usize;
+            for n in (0..=draws).rev() {
+                for k in 1..=left.min(n) {
+                    ways[n] += ways[n - k] * binomial(n, k);
+                }
+            }
+        }
+        ways[draws]
+    }
+
 
 }
 
 
+fn binomial(n: usize, k: usize) -> usize {
+    (0..k).fold(1, |binomial, i| binomial * (n - i) / (i + 1))
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct InvalidTokenError;
 
+/// Parse a single shape name, in either case.
+pub fn parse_shape(shape: &str) -> Result<Shape, String> {
+    Shape::ALL
+        .into_iter()
+        .find(|candidate| candidate.name() == shape.to_uppercase())
+        .ok_or_else(|| format!("{shape} is not one of IJLOSTZ"))
+}
+
 impl FromStr for CombinatoricQueue {
     type Err = InvalidTokenError;
 
@@ -331,6 +495,23 @@ impl FromStr for CombinatoricQueue {
         let s = s.replace("*", "[IJLOSTZ]");
         let mut char_iter = s.chars().peekable();
         let mut inverted = false;
+        if char_iter.peek() == Some(&'('){
+            char_iter.next();
+            let shape = match char_iter.next(){
+                Some('I') => Shape::I,
+                Some('O') => Shape::O,
+                Some('T') => Shape::T,
+                Some('L') => Shape::L,
+                Some('J') => Shape::J,
+                Some('S') => Shape::S,
+                Some('Z') => Shape::Z,
+                _ => return Err(InvalidTokenError),
+            };
+            if char_iter.next() != Some(')'){
+                return Err(InvalidTokenError);
+            }
+            queue.set_hold(shape);
+        }
         while let Some(c) = char_iter.next() {
             match c {
                 'I' | 'O' | 'T' | 'L' | 'J' | 'S' | 'Z' => {
@@ -412,9 +593,49 @@ impl FromStr for CombinatoricQueue {
                     current_bag = Vec::new();
                     inverted = false;
                 }
+                // a starting hold is only allowed at the front
+                '(' | ')' => return Err(InvalidTokenError),
                 _ => {}
             }
         }
         Ok(queue)
     }
 }
+
+#[test]
+fn counting(){
+    for pattern in ["[IJSZ]!IJ*p3", "[SSZZ]!", "*p4,[OOIT]p3", "(T)[IJLO]!,*p2"]{
+        let queue = CombinatoricQueue::from_str(pattern).unwrap();
+        let counted_bags = queue.get_counted_bags();
+        let start = QueueState(counted_bags.first().unwrap().1.full);
+        let mut permus = get_queue_permutations(&counted_bags, None, None);
+        assert_eq!(count_queues(&counted_bags, start, 0, counted_bags.len()), permus.len());
+        assert_eq!(queue.queue_count(), permus.len());
+
+        // Part way through a bag, and stopping part way through another
+        for depth in 0..=counted_bags.len(){
+            let prefixes = get_queue_permutations(&counted_bags, None, Some(depth));
+            assert_eq!(count_queues(&counted_bags, start, 0, depth), prefixes.len());
+        }
+        let first = permus[0][0];
+        let state = start.next(&counted_bags[0].1).take(&counted_bags[0].1, first).unwrap();
+        let rest = get_queue_permutations(&counted_bags, Some((1, state)), None);
+        assert_eq!(count_queues(&counted_bags, state, 1, counted_bags.len()), rest.len());
+
+        for (index, permu) in permus.iter_mut().enumerate(){
+            assert_eq!(queue.rank(permu.make_contiguous()), Some(index));
+            assert_eq!(queue.unrank(index).as_ref(), Some(&*permu));
+        }
+        assert_eq!(queue.unrank(permus.len()), None);
+
+        // a prefix ranks where the first full queue starting with it does
+        for prefix in get_queue_permutations(&counted_bags, None, Some(2)){
+            let first = permus.iter().position(|permu| permu.iter().take(2).eq(prefix.iter()));
+            assert_eq!(queue.prefix_rank(&Vec::from(prefix)), first);
+        }
+    }
+
+    let queue = CombinatoricQueue::from_str("[SSZZ]!").unwrap();
+    assert_eq!(queue.rank(&[Shape::S, Shape::S, Shape::S, Shape::Z]), None);
+    assert_eq!(queue.rank(&[Shape::S, Shape::Z]), None);
+}
@@ -0,0 +1,338 @@
+//! Answer chance, path and cover queries against one loaded graph, as JSON
+//! lines on stdin and stdout, so tools don't pay for loading the shards on
+//! every query.
+//!
+//! Each request is an object with an `id` and a `type`, plus the fields for
+//! that type:
+//!
+//! - `chance` takes the fields of a [batch job](crate::batch::Job)
+//! - `path` takes `pattern`, and optionally `fumen` and `hold`
+//! - `cover` takes `pattern`, and optionally `solutions` as fumens, `fumen`
+//!   and `hold`.  Without solutions, every solution from `fumen` is covered.
+//! - `cancel` takes the `target` id of a running query
+//!
+//! Queries run at the same time, and each answers with one `result`, `error`
+//! or `cancelled` event carrying its id.  Running chance queries also send
+//! `progress` events.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use legal_boards::boardgraph::{FrozenGigapan, GigapanLookup};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use srs_4l::brokenboard::BrokenBoard;
+use srs_4l::gameplay::Board;
+
+use crate::batch::{empty_board, yes, Job};
+use crate::calculate::{Progress, Stop};
+use crate::cover::CoverMatrix;
+use crate::fumens::{decode_fumen, decode_solutions, encode_broken_boards};
+use crate::path::find_solutions;
+use crate::queue::CombinatoricQueue;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathQuery {
+    #[serde(default = "empty_board")]
+    pub fumen: String,
+    pub pattern: String,
+    #[serde(default = "yes")]
+    pub hold: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CoverQuery {
+    #[serde(default = "empty_board")]
+    pub fumen: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub solutions: Vec<String>,
+    #[serde(default = "yes")]
+    pub hold: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CancelRequest {
+    target: Value,
+}
+
+#[derive(Debug, Clone)]
+pub enum Query {
+    Chance(Job),
+    Path(PathQuery),
+    Cover(CoverQuery),
+}
+
+enum Request {
+    Query(Query),
+    Cancel(Value),
+}
+
+/// Split a request line into its id and what it asks for.  The id is still
+/// returned when the rest can't be understood, so the error can be sent back.
+fn parse_request(line: &str) -> (Value, Result<Request, String>) {
+    let mut object = match serde_json::from_str::<Value>(line) {
+        Ok(Value::Object(object)) => object,
+        Ok(_) => return (Value::Null, Err("a request must be a json object".to_string())),
+        Err(error) => return (Value::Null, Err(error.to_string())),
+    };
+    let id = object.remove("id").unwrap_or(Value::Null);
+    if id.is_null() {
+        return (id, Err("a request needs an id".to_string()));
+    }
+    let kind = match object.remove("type") {
+        Some(Value::String(kind)) => kind,
+        _ => return (id, Err("a request needs a type".to_string())),
+    };
+
+    let fields = Value::Object(object);
+    let request = match kind.as_str() {
+        "chance" => serde_json::from_value(fields).map(|job| Request::Query(Query::Chance(job))),
+        "path" => serde_json::from_value(fields).map(|query| Request::Query(Query::Path(query))),
+        "cover" => serde_json::from_value(fields).map(|query| Request::Query(Query::Cover(query))),
+        "cancel" => serde_json::from_value(fields).map(|cancel: CancelRequest| Request::Cancel(cancel.target)),
+        kind => return (id, Err(format!("unknown request type {kind}"))),
+    };
+    (id, request.map_err(|error| error.to_string()))
+}
+
+fn parse_board(fumen: &str) -> Result<Board, String> {
+    decode_fumen(fumen)
+        .map(Board)
+        .ok_or_else(|| format!("{fumen} is not a fumen of the bottom 4 rows"))
+}
+
+fn parse_pattern(pattern: &str) -> Result<CombinatoricQueue, String> {
+    CombinatoricQueue::from_str(pattern).map_err(|_| format!("{pattern} is not a valid pattern"))
+}
+
+fn solvable(gigapan: &FrozenGigapan, board: Board, fumen: &str) -> Result<(), String> {
+    match gigapan.edges(board) {
+        Some(_) => Ok(()),
+        None => Err(format!("no perfect clear is possible from {fumen}, or it wasn't loaded")),
+    }
+}
+
+impl Query {
+    /// When the query may stop early, which only chance queries can
+    pub fn stop(&self) -> Stop {
+        match self {
+            Query::Chance(job) => job.stop().unwrap_or_default(),
+            Query::Path(_) | Query::Cover(_) => Stop::default(),
+        }
+    }
+
+    /// Answer the query.  Only chance queries stop early when cancelled.
+    pub fn run(&self, gigapan: &FrozenGigapan, progress: &Progress) -> Result<Value, String> {
+        match self {
+            Query::Chance(job) => job.run(gigapan, progress).map(|result| result.summary()),
+            Query::Path(query) => {
+                let board = parse_board(&query.fumen)?;
+                let queue = parse_pattern(&query.pattern)?;
+                solvable(gigapan, board, &query.fumen)?;
+                let solutions: Vec<Value> = find_solutions(gigapan, board, &queue, query.hold)
+                    .iter()
+                    .map(|solution| {
+                        json!({
+                            "fumen": encode_broken_boards([(&solution.board, None)]),
+                            "covered": solution.covered,
+                        })
+                    })
+                    .collect();
+                Ok(json!({"total": queue.queue_count(), "solutions": solutions}))
+            }
+            Query::Cover(query) => {
+                let queue = parse_pattern(&query.pattern)?;
+                let solutions: Vec<BrokenBoard> = if query.solutions.is_empty() {
+                    let board = parse_board(&query.fumen)?;
+                    solvable(gigapan, board, &query.fumen)?;
+                    find_solutions(gigapan, board, &queue, query.hold)
+                        .into_iter()
+                        .map(|solution| solution.board)
+                        .collect()
+                } else {
+                    let mut solutions = Vec::new();
+                    for fumen in &query.solutions {
+                        let decoded = decode_solutions(fumen).ok_or_else(|| format!("{fumen} is not a solution fumen"))?;
+                        solutions.extend(decoded);
+                    }
+                    solutions
+                };
+
+                let matrix = CoverMatrix::compute(solutions, &queue, query.hold);
+                let solutions: Vec<Value> = matrix
+                    .solutions
+                    .iter()
+                    .zip(matrix.solution_counts())
+                    .map(|(solution, covered)| {
+                        json!({
+                            "fumen": encode_broken_boards([(solution, None)]),
+                            "covered": covered,
+                        })
+                    })
+                    .collect();
+                Ok(json!({"total": matrix.queues.len(), "any": matrix.any_count(), "solutions": solutions}))
+            }
+        }
+    }
+}
+
+/// Events go out one line at a time, whichever thread sends them
+struct Events<W: Write>(Mutex<W>);
+
+impl<W: Write> Events<W> {
+    fn send(&self, id: &Value, event: &str, mut fields: Value) {
+        fields["id"] = id.clone();
+        fields["event"] = event.into();
+        let mut to = self.0.lock().unwrap();
+        // a closed output only means nobody is listening any more
+        let _ = serde_json::to_writer(&mut *to, &fields);
+        let _ = writeln!(to);
+        let _ = to.flush();
+    }
+}
+
+/// Answer requests from `input` until it ends, then wait for the running
+/// queries.  Progress is sent every `interval`.
+pub fn serve(gigapan: &FrozenGigapan, input: impl BufRead, output: impl Write + Send, interval: Duration) -> std::io::Result<()> {
+    let events = Events(Mutex::new(output));
+    // running queries by their id, as json text
+    let running: Mutex<HashMap<String, (Value, Arc<Progress>)>> = Mutex::new(HashMap::new());
+    let reading = AtomicBool::new(true);
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            let mut last = Instant::now();
+            while reading.load(Ordering::Relaxed) || !running.lock().unwrap().is_empty() {
+                std::thread::sleep(Duration::from_millis(10).min(interval));
+                if last.elapsed() < interval {
+                    continue;
+                }
+                last = Instant::now();
+                for (id, progress) in running.lock().unwrap().values() {
+                    if progress.total() > 0 && !progress.is_cancelled() {
+                        events.send(
+                            id,
+                            "progress",
+                            json!({
+                                "done": progress.done(),
+                                "total": progress.total(),
+                                "lower": progress.lower(),
+                                "upper": progress.upper(),
+                            }),
+                        );
+                    }
+                }
+            }
+        });
+
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    reading.store(false, Ordering::Relaxed);
+                    return Err(error);
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (id, request) = parse_request(&line);
+            let query = match request {
+                Err(message) => {
+                    events.send(&id, "error", json!({"message": message}));
+                    continue;
+                }
+                Ok(Request::Cancel(target)) => {
+                    match running.lock().unwrap().get(&target.to_string()) {
+                        Some((_, progress)) => progress.cancel(),
+                        None => events.send(&id, "error", json!({"message": format!("no query {target} is running")})),
+                    }
+                    continue;
+                }
+                Ok(Request::Query(query)) => query,
+            };
+
+            let key = id.to_string();
+            let progress = Arc::new(Progress::with_stop(query.stop()));
+            {
+                let mut running = running.lock().unwrap();
+                if running.contains_key(&key) {
+                    drop(running);
+                    events.send(&id, "error", json!({"message": format!("query {key} is already running")}));
+                    continue;
+                }
+                running.insert(key.clone(), (id.clone(), progress.clone()));
+            }
+
+            let (events, running) = (&events, &running);
+            scope.spawn(move || {
+                let result = query.run(gigapan, &progress);
+                running.lock().unwrap().remove(&key);
+                match result {
+                    _ if progress.is_cancelled() => events.send(&id, "cancelled", json!({})),
+                    Ok(result) => events.send(&id, "result", json!({"result": result})),
+                    Err(message) => events.send(&id, "error", json!({"message": message})),
+                }
+            });
+        }
+
+        reading.store(false, Ordering::Relaxed);
+        Ok(())
+    })
+}
+
+#[test]
+fn queries() {
+    let (_, gigapan) = crate::fixture::small_board();
+
+    let input = r#"
+{"id": 1, "type": "chance", "fumen": "v115@ThH8BeH8JeAgH", "pattern": "O,*p6", "previews": 1}
+{"id": "p", "type": "path", "fumen": "v115@ThH8BeH8JeAgH", "pattern": "OOOOOOO"}
+{"id": 3, "type": "cover", "fumen": "v115@ThH8BeH8JeAgH", "pattern": "[OOOOIIT]p7"}
+{"id": 4, "type": "chance", "pattern": "*p7", "lines": 3}
+{"id": 5, "type": "fly"}
+{"type": "path"}
+{"id": 6, "type": "cancel", "target": 99}
+"#;
+    let mut output = Vec::new();
+    serve(&gigapan, input.as_bytes(), &mut output, Duration::from_secs(60)).unwrap();
+
+    let events: HashMap<String, Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .map(|event| (event["id"].to_string(), event))
+        .collect();
+    assert_eq!(events.len(), 7);
+    assert_eq!(events["1"]["event"], "result");
+    assert_eq!(events["1"]["result"]["passing"], 600);
+    assert_eq!(events["\"p\""]["result"]["solutions"].as_array().unwrap().len(), 1);
+    let cover = &events["3"]["result"];
+    assert_eq!(cover["any"], cover["total"]);
+    for id in ["4", "5", "null", "6"] {
+        assert_eq!(events[id]["event"], "error");
+    }
+}
+
+#[test]
+fn cancelling() {
+    let (_, gigapan) = crate::fixture::small_board();
+    let job: Job = serde_json::from_str(r#"{"fumen": "v115@ThH8BeH8JeAgH", "pattern": "O,*p6", "previews": 1}"#).unwrap();
+
+    let progress = Progress::default();
+    progress.cancel();
+    assert_eq!(Query::Chance(job.clone()).run(&gigapan, &progress), Err("cancelled".to_string()));
+
+    let progress = Progress::default();
+    assert!(Query::Chance(job).run(&gigapan, &progress).is_ok());
+    assert_eq!((progress.done(), progress.total()), (42, 42));
+}
@@ -0,0 +1,169 @@
+//! Winning placement sequences for set queues, so players can see how a queue
+//! is solved and not just whether it can be.
+
+use std::collections::VecDeque;
+use std::io::Write;
+
+use hashbrown::HashSet;
+use legal_boards::boardgraph::FrozenGigapan;
+use rand::{rngs::StdRng, seq::index, SeedableRng};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use srs_4l::gameplay::{Board, Shape};
+
+use crate::calculate::{test_set_queue_with_hold, test_set_queue_without_hold};
+use crate::fumens::encode_path;
+use crate::queue::{get_queue_permutations, CombinatoricQueue};
+
+/// One placement on the way to a perfect clear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub shape: Shape,
+    /// The board after placing the piece
+    pub board: Board,
+    /// Whether the piece was swapped out of hold
+    pub held: bool,
+}
+
+/// A queue and how it is solved, if it is
+pub struct Solution {
+    pub queue: VecDeque<Shape>,
+    pub steps: Option<Vec<Step>>,
+}
+
+impl Solution {
+    /// A fumen of the starting board followed by every placement
+    pub fn fumen(&self, start: Board) -> Option<String> {
+        let steps = self.steps.as_ref()?;
+        let queue: String = self.queue.iter().map(|shape| shape.name()).collect();
+        Some(encode_path(
+            start,
+            Some(queue),
+            steps.iter().map(|step| {
+                let comment = if step.held {
+                    format!("{} (hold)", step.shape.name())
+                } else {
+                    step.shape.name().to_string()
+                };
+                (step.shape, step.board, Some(comment))
+            }),
+        ))
+    }
+}
+
+/// Every full queue of the pattern, or `sample` random ones of them
+pub fn pick_queues(combinatoric_queue: &CombinatoricQueue, sample: Option<usize>, seed: u64) -> Vec<VecDeque<Shape>> {
+    match sample {
+        Some(sample) => {
+            // pick by index so the other queues are never built
+            let count = combinatoric_queue.queue_count();
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut indices = index::sample(&mut rng, count, sample.min(count)).into_vec();
+            indices.sort_unstable();
+            indices
+                .into_iter()
+                .map(|index| combinatoric_queue.unrank(index).unwrap())
+                .collect()
+        }
+        None => get_queue_permutations(&combinatoric_queue.get_counted_bags(), None, None),
+    }
+}
+
+/// Solve each of the set queues.  Hold works as in
+/// [`limited_see_chance`](crate::calculate::limited_see_chance), except that
+/// the whole queue is seen from the start, and only boards in `culled` are
+/// used if it is given.
+pub fn solve_queues(
+    gigapan: &FrozenGigapan,
+    board: Board,
+    queues: Vec<VecDeque<Shape>>,
+    culled: Option<&HashSet<Board>>,
+    init_hold: bool,
+    use_hold: bool,
+    two_line: bool,
+) -> Vec<Solution> {
+    queues
+        .into_par_iter()
+        .map(|mut queue| {
+            let steps = solve_queue(gigapan, culled, board, &mut queue, init_hold, use_hold, two_line);
+            Solution { queue, steps }
+        })
+        .collect()
+}
+
+/// Find a placement sequence for one set queue
+pub fn solve_queue(
+    gigapan: &FrozenGigapan,
+    culled: Option<&HashSet<Board>>,
+    board: Board,
+    queue: &mut VecDeque<Shape>,
+    init_hold: bool,
+    use_hold: bool,
+    two_line: bool,
+) -> Option<Vec<Step>> {
+    let mut steps = Vec::new();
+    let found = if use_hold {
+        let hold = queue.pop_front()?;
+        let found = test_set_queue_with_hold(gigapan, culled, board, queue, hold, two_line, Some(&mut steps));
+        queue.push_front(hold);
+        found
+    } else if init_hold {
+        let hold = queue.pop_front()?;
+        let found = test_set_queue_without_hold(gigapan, culled, board, queue, two_line, Some(&mut steps));
+        queue.push_front(hold);
+        found
+    } else {
+        test_set_queue_without_hold(gigapan, culled, board, queue, two_line, Some(&mut steps))
+    };
+    if found {
+        Some(steps)
+    } else {
+        None
+    }
+}
+
+/// Write the solutions as csv, with an empty fumen for queues without one
+pub fn write_solutions(solutions: &[Solution], start: Board, mut to: impl Write) -> std::io::Result<()> {
+    writeln!(to, "queue,fumen")?;
+    for solution in solutions {
+        let queue: String = solution.queue.iter().map(|shape| shape.name()).collect();
+        let fumen = solution.fumen(start).unwrap_or_default();
+        writeln!(to, "{queue},{fumen}")?;
+    }
+    to.flush()
+}
+
+#[test]
+fn replays() {
+    use legal_boards::boardgraph::GigapanLookup;
+    use std::str::FromStr;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let queue = CombinatoricQueue::from_str("[OOOOIIT]p7").unwrap();
+
+    let culled = crate::calculate::culled_boards(&gigapan, board, &queue, true);
+    let solutions = solve_queues(&gigapan, board, pick_queues(&queue, None, 0), Some(&culled), true, true, false);
+    assert!(solutions.iter().all(|solution| solution.steps.is_some()));
+
+    for solution in solutions {
+        let steps = solution.steps.unwrap();
+        assert_eq!(steps.last().unwrap().board, Board::full());
+
+        // Placing each piece in order, holding when a step says so, must use
+        // up the queue.
+        let mut queue = solution.queue.clone();
+        let mut hold = queue.pop_front().unwrap();
+        let mut parent = board;
+        for step in steps {
+            let current = queue.pop_front().unwrap();
+            if step.held {
+                assert_eq!(step.shape, hold);
+                hold = current;
+            } else {
+                assert_eq!(step.shape, current);
+            }
+            assert!(gigapan.edges(parent).unwrap().contains(step.shape, step.board));
+            assert!(step.board == Board::full() || culled.contains(&step.board));
+            parent = step.board;
+        }
+    }
+}
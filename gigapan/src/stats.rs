@@ -0,0 +1,305 @@
+//! Statistics and export for gigapan graphs.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+
+use hashbrown::{HashMap, HashSet};
+use legal_boards::boardgraph::FrozenGigapan;
+use srs_4l::gameplay::{Board, Shape};
+
+const FULL: Board = Board(0xFFFFF_FFFFF);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Dot,
+}
+
+pub struct GraphStats {
+    /// Number of nodes, indexed by mino count
+    pub nodes_per_minos: [usize; 41],
+    /// For each shape, how many nodes have a given number of children
+    pub out_degree: [BTreeMap<usize, usize>; 7],
+    /// Number of distinct placement sequences from each node to a perfect clear
+    pub paths: HashMap<Board, u128>,
+    /// Nodes without any children
+    pub dead_ends: Vec<Board>,
+    /// Children which are neither nodes nor a perfect clear
+    pub dangling: Vec<Board>,
+}
+
+impl GraphStats {
+    pub fn compute(gigapan: &FrozenGigapan) -> Self {
+        let mut nodes_per_minos = [0; 41];
+        let mut out_degree: [BTreeMap<usize, usize>; 7] = Default::default();
+        let mut dead_ends = Vec::new();
+        let mut dangling = HashSet::new();
+
+        for (&board, edges) in gigapan.iter() {
+            nodes_per_minos[board.0.count_ones() as usize] += 1;
+            for (shape, children) in edges.iter().enumerate() {
+                *out_degree[shape].entry(children.len()).or_default() += 1;
+                for child in children {
+                    if *child != FULL && gigapan.get(child).is_none() {
+                        dangling.insert(*child);
+                    }
+                }
+            }
+            if edges.iter().all(Vec::is_empty) {
+                dead_ends.push(board);
+            }
+        }
+        dead_ends.sort_unstable();
+        let mut dangling: Vec<Board> = dangling.into_iter().collect();
+        dangling.sort_unstable();
+
+        GraphStats {
+            nodes_per_minos,
+            out_degree,
+            paths: count_paths(gigapan),
+            dead_ends,
+            dangling,
+        }
+    }
+
+    /// The `n` nodes with the most paths to a perfect clear, most first
+    pub fn most_paths(&self, n: usize) -> Vec<(Board, u128)> {
+        let mut paths: Vec<(Board, u128)> = self.paths.iter().map(|(&b, &p)| (b, p)).collect();
+        paths.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        paths.truncate(n);
+        paths
+    }
+
+    /// The `n` nodes with the fewest paths to a perfect clear, fewest first
+    pub fn fewest_paths(&self, n: usize) -> Vec<(Board, u128)> {
+        let mut paths: Vec<(Board, u128)> = self.paths.iter().map(|(&b, &p)| (b, p)).collect();
+        paths.sort_unstable_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+        paths.truncate(n);
+        paths
+    }
+
+    pub fn print(&self, top: usize) {
+        println!("nodes per mino count:");
+        for (minos, &count) in self.nodes_per_minos.iter().enumerate() {
+            if count != 0 {
+                println!("{:>4} minos: {:>10}", minos, count);
+            }
+        }
+        println!("total nodes: {}", self.nodes_per_minos.iter().sum::<usize>());
+
+        println!("out-degree per shape (children: nodes):");
+        for shape in Shape::ALL {
+            let histogram: Vec<String> = self.out_degree[shape as usize]
+                .iter()
+                .map(|(degree, count)| format!("{degree}:{count}"))
+                .collect();
+            println!("{:>4}: {}", shape.name(), histogram.join(" "));
+        }
+
+        println!("most paths to a pc:");
+        for (board, paths) in self.most_paths(top) {
+            println!("{paths} paths{board}");
+        }
+        println!("fewest paths to a pc:");
+        for (board, paths) in self.fewest_paths(top) {
+            println!("{paths} paths{board}");
+        }
+
+        println!("dead ends: {}", self.dead_ends.len());
+        for board in self.dead_ends.iter().take(top) {
+            println!("{board}");
+        }
+        println!("dangling children: {}", self.dangling.len());
+        for board in self.dangling.iter().take(top) {
+            println!("{board}");
+        }
+    }
+}
+
+/// Count the placement sequences leading from each node to a perfect clear.
+/// Every placement adds four minoes, so nodes can be processed from the most
+/// filled down.
+pub fn count_paths(gigapan: &FrozenGigapan) -> HashMap<Board, u128> {
+    let mut by_minos: Vec<Vec<Board>> = vec![Vec::new(); 41];
+    for (&board, _) in gigapan.iter() {
+        by_minos[board.0.count_ones() as usize].push(board);
+    }
+
+    let mut paths: HashMap<Board, u128> = HashMap::new();
+    paths.insert(FULL, 1);
+    for boards in by_minos.iter().rev() {
+        for board in boards {
+            let edges = gigapan.get(board).unwrap();
+            let count = edges
+                .iter()
+                .flatten()
+                .map(|child| paths.get(child).copied().unwrap_or(0))
+                .fold(0u128, u128::saturating_add);
+            paths.insert(*board, count);
+        }
+    }
+    paths.remove(&FULL);
+    paths
+}
+
+/// The nodes reachable from `root`, including `root` itself if it is a node
+pub fn reachable(gigapan: &FrozenGigapan, root: Board) -> HashSet<Board> {
+    let mut seen = HashSet::new();
+    let mut work = VecDeque::new();
+    if gigapan.get(&root).is_some() {
+        seen.insert(root);
+        work.push_back(root);
+    }
+    while let Some(board) = work.pop_front() {
+        for &child in gigapan.get(&board).unwrap().iter().flatten() {
+            if gigapan.get(&child).is_some() && seen.insert(child) {
+                work.push_back(child);
+            }
+        }
+    }
+    seen
+}
+
+/// Write the edges of the graph, or only of the part reachable from `root`.
+/// Boards are written as their bitboard integers.
+pub fn export(
+    gigapan: &FrozenGigapan,
+    root: Option<Board>,
+    format: ExportFormat,
+    mut to: impl Write,
+) -> std::io::Result<()> {
+    let mut nodes: Vec<Board> = match root {
+        Some(root) => reachable(gigapan, root).into_iter().collect(),
+        None => gigapan.iter().map(|(&board, _)| board).collect(),
+    };
+    nodes.sort_unstable();
+
+    let edges = nodes.iter().flat_map(|&parent| {
+        let edges = gigapan.get(&parent).unwrap();
+        Shape::ALL.into_iter().flat_map(move |shape| {
+            edges[shape as usize]
+                .iter()
+                .map(move |&child| (parent, shape, child))
+        })
+    });
+
+    match format {
+        ExportFormat::Csv => {
+            writeln!(to, "parent,shape,child")?;
+            for (parent, shape, child) in edges {
+                writeln!(to, "{},{},{}", parent.0, shape.name(), child.0)?;
+            }
+        }
+        ExportFormat::Json => {
+            let nodes: Vec<u64> = nodes.iter().map(|board| board.0).collect();
+            let edges: Vec<serde_json::Value> = edges
+                .map(|(parent, shape, child)| {
+                    serde_json::json!({"parent": parent.0, "shape": shape.name(), "child": child.0})
+                })
+                .collect();
+            serde_json::to_writer(&mut to, &serde_json::json!({"nodes": nodes, "edges": edges}))?;
+            writeln!(to)?;
+        }
+        ExportFormat::Dot => {
+            writeln!(to, "digraph gigapan {{")?;
+            for (parent, shape, child) in edges {
+                writeln!(to, "    b{} -> b{} [label=\"{}\"];", parent.0, child.0, shape.name())?;
+            }
+            writeln!(to, "}}")?;
+        }
+    }
+    to.flush()
+}
+
+#[test]
+fn hand_built() {
+    use legal_boards::boardgraph::Gigapan;
+
+    // the edges don't have to be real placements, only the mino counts matter
+    let a = Board(FULL.0 & !0xFF);
+    let b = Board(FULL.0 & !0xF0);
+    let c = Board(FULL.0 & !0x0F);
+    let dead_end = Board(FULL.0 & !0xF00);
+    let dangling = Board(FULL.0 & !0xF000);
+    let gigapan = Gigapan::new();
+    let node = |children: &[(Shape, Vec<Board>)]| {
+        let mut edges: [Vec<Board>; 7] = Default::default();
+        for (shape, boards) in children {
+            edges[*shape as usize] = boards.clone();
+        }
+        edges
+    };
+    gigapan.insert(a, node(&[(Shape::I, vec![b, c]), (Shape::T, vec![dangling])]));
+    gigapan.insert(b, node(&[(Shape::I, vec![FULL])]));
+    gigapan.insert(c, node(&[(Shape::I, vec![FULL]), (Shape::O, vec![FULL])]));
+    gigapan.insert(dead_end, node(&[]));
+    let gigapan = gigapan.freeze();
+
+    let stats = GraphStats::compute(&gigapan);
+    assert_eq!((stats.nodes_per_minos[32], stats.nodes_per_minos[36]), (1, 3));
+    assert_eq!(stats.nodes_per_minos.iter().sum::<usize>(), 4);
+    assert_eq!(stats.out_degree[Shape::I as usize], BTreeMap::from([(0, 1), (1, 2), (2, 1)]));
+    assert_eq!(stats.out_degree[Shape::T as usize], BTreeMap::from([(0, 3), (1, 1)]));
+    assert_eq!(stats.out_degree[Shape::J as usize], BTreeMap::from([(0, 4)]));
+    // b has one path, c one for each of its two placements, and a one
+    // through b and two through c
+    assert_eq!(stats.paths, HashMap::from([(a, 3), (b, 1), (c, 2), (dead_end, 0)]));
+    assert_eq!(stats.most_paths(2), vec![(a, 3), (c, 2)]);
+    assert_eq!(stats.fewest_paths(1), vec![(dead_end, 0)]);
+    assert_eq!(stats.dead_ends, vec![dead_end]);
+    assert_eq!(stats.dangling, vec![dangling]);
+
+    assert_eq!(reachable(&gigapan, a), HashSet::from([a, b, c]));
+    assert!(reachable(&gigapan, dangling).is_empty());
+
+    let mut csv = Vec::new();
+    export(&gigapan, Some(a), ExportFormat::Csv, &mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(), "\
+parent,shape,child
+1099511627520,I,1099511627535
+1099511627520,I,1099511627760
+1099511627520,T,1099511566335
+1099511627535,I,1099511627775
+1099511627760,I,1099511627775
+1099511627760,O,1099511627775
+");
+}
+
+#[test]
+fn small_board() {
+    use srs_4l::vector::Placements;
+
+    // Placement sequences to a perfect clear, trying every placement
+    fn brute_force(board: Board, memo: &mut HashMap<Board, u128>) -> u128 {
+        if board == FULL {
+            return 1;
+        }
+        if let Some(&known) = memo.get(&board) {
+            return known;
+        }
+        let mut paths = 0;
+        for shape in Shape::ALL {
+            let mut children: Vec<Board> = Placements::place(board, shape).canonical().map(|(_, child)| child).collect();
+            children.sort_unstable();
+            children.dedup();
+            paths += children.into_iter().map(|child| brute_force(child, memo)).sum::<u128>();
+        }
+        memo.insert(board, paths);
+        paths
+    }
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let stats = GraphStats::compute(&gigapan);
+    assert_eq!(stats.nodes_per_minos.iter().sum::<usize>(), gigapan.len());
+    assert_eq!(stats.nodes_per_minos[..16].iter().sum::<usize>(), 0);
+    assert_eq!(stats.nodes_per_minos[16], 1);
+    assert!(stats.dead_ends.is_empty() && stats.dangling.is_empty());
+    assert_eq!(reachable(&gigapan, board).len(), gigapan.len());
+
+    let mut memo = HashMap::new();
+    for (&node, &paths) in &stats.paths {
+        assert_eq!(paths, brute_force(node, &mut memo), "{node}");
+    }
+    assert!(stats.paths[&board] > 0);
+}
@@ -0,0 +1,294 @@
+//! The limited see strategy itself: the best move at every point of the
+//! queue, for each way the pieces can be revealed, as a decision tree.
+//!
+//! Each node is a position where a move is chosen.  Its children are the
+//! positions after the best move, one for each piece that can be revealed
+//! next.  Following the tree plays for the chance
+//! [`limited_see_chance`](crate::calculate::limited_see_chance) finds.
+
+use std::collections::VecDeque;
+use std::io::Write;
+
+use legal_boards::boardgraph::{FrozenGigapan, GigapanLookup};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde_json::{json, Value};
+use srs_4l::gameplay::{Board, Shape};
+
+use crate::advise::{Move, Position};
+use crate::calculate::{culled_boards, fills_board, ChanceOptions, LimitedSeeMemo, Search};
+use crate::fumens::{encode_boards, encode_placements};
+use crate::queue::{get_queue_permutations, CombinatoricQueue};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StrategyFormat {
+    Text,
+    Json,
+    Fumen,
+}
+
+/// A position, with the best move from it and what follows
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub position: Position,
+    /// The best move, or `None` once the perfect clear is done or no move
+    /// passes any hidden queue
+    pub action: Option<Move>,
+    /// Hidden queues which pass with the best move
+    pub passing: usize,
+    pub total: usize,
+    /// The positions after the move, by the piece revealed next, or `None`
+    /// once there's nothing left to reveal.  Empty past the depth limit.
+    pub next: Vec<(Option<Shape>, Node)>,
+}
+
+/// The strategy for every queue revealed at the start
+pub struct Strategy {
+    pub board: Board,
+    pub pattern: String,
+    pub previews: usize,
+    pub init_hold: bool,
+    pub use_hold: bool,
+    pub culled: bool,
+    pub two_line: bool,
+    /// The first position by the pieces revealed before the first move
+    pub openings: Vec<(VecDeque<Shape>, Node)>,
+    pub passing: usize,
+    pub total: usize,
+}
+
+/// Find the best move at every position reached by following the best moves,
+/// up to `depth` moves in if given.  Takes the same settings as
+/// [`limited_see_chance`](crate::calculate::limited_see_chance), without the
+/// checkpoint and progress.
+pub fn limited_see_strategy(
+    gigapan: &FrozenGigapan,
+    board: Board,
+    combinatoric_queue: &CombinatoricQueue,
+    options: &ChanceOptions,
+    depth: Option<usize>,
+) -> Result<Strategy, String> {
+    let &ChanceOptions { previews, use_hold, two_line, .. } = options;
+    if !fills_board(board, combinatoric_queue) {
+        return Err(format!("{combinatoric_queue} doesn't have the right number of pieces for the board"));
+    }
+    if gigapan.edges(board).is_none() {
+        return Err(format!("no perfect clear is possible from {board}"));
+    }
+    let counted_bags = combinatoric_queue.get_counted_bags();
+    let init_hold = options.init_hold || combinatoric_queue.hold().is_some();
+    let most = counted_bags.len() - 1 - init_hold as usize;
+    if previews > most {
+        return Err(format!("the pattern only allows up to {most} previews"));
+    }
+    let revealed = previews + 1 + init_hold as usize;
+    let culled = options.culled.then(|| culled_boards(gigapan, board, combinatoric_queue, use_hold));
+    let culled = culled.as_ref();
+
+    let memo = LimitedSeeMemo::new();
+    let search = Search { gigapan, culled, memo: &memo, counted_bags: &counted_bags, use_hold, two_line };
+    let tree = Tree { search, two_line, depth };
+
+    let openings: Vec<(VecDeque<Shape>, Node)> = get_queue_permutations(&counted_bags, None, Some(revealed))
+        .into_par_iter()
+        .map(|queue| {
+            let mut seen: Vec<Shape> = queue.iter().copied().collect();
+            let hold = if init_hold { Some(seen.remove(0)) } else { None };
+            let position = Position::start(board, &counted_bags, hold, &seen).expect("revealed queues start the pattern");
+            let node = tree.node(position, 0);
+            (queue, node)
+        })
+        .collect();
+
+    Ok(Strategy {
+        board,
+        pattern: combinatoric_queue.to_string(),
+        previews,
+        init_hold,
+        use_hold,
+        culled: culled.is_some(),
+        two_line,
+        passing: openings.iter().map(|(_, node)| node.passing).sum(),
+        total: combinatoric_queue.queue_count(),
+        openings,
+    })
+}
+
+struct Tree<'a> {
+    search: Search<'a>,
+    two_line: bool,
+    depth: Option<usize>,
+}
+
+impl Tree<'_> {
+    fn node(&self, position: Position, moves: usize) -> Node {
+        let total = self.search.total(&position);
+        if position.is_done(self.two_line) {
+            return Node { position, action: None, passing: total, total, next: Vec::new() };
+        }
+
+        let best = match self.search.moves(&position).into_iter().next() {
+            Some(best) if best.passing > 0 => best,
+            _ => return Node { position, action: None, passing: 0, total, next: Vec::new() },
+        };
+        let next = if self.depth.is_some_and(|depth| moves + 1 >= depth) {
+            Vec::new()
+        } else {
+            self.search
+                .reveal(&position.after(best.action))
+                .into_par_iter()
+                .map(|(shape, next)| (shape, self.node(next, moves + 1)))
+                .collect()
+        };
+        Node { position, action: Some(best.action), passing: best.passing, total, next }
+    }
+}
+
+fn queue_name(position: &Position) -> String {
+    let hold = position.hold.map_or(String::new(), |shape| format!("({})", shape.name()));
+    let queue: String = position.queue.iter().map(|shape| shape.name()).collect();
+    format!("{hold}{queue}")
+}
+
+fn revealed_name(shape: Option<Shape>) -> &'static str {
+    shape.map_or("-", |shape| shape.name())
+}
+
+impl Node {
+    fn describe(&self) -> String {
+        match self.action {
+            Some(action) => format!("{} {}/{}", action, self.passing, self.total),
+            None if self.passing > 0 => "done".to_string(),
+            None => format!("no move passes, 0/{}", self.total),
+        }
+    }
+
+    /// The node as json, with the next positions keyed by the piece revealed,
+    /// or under `then` when nothing is revealed
+    pub fn to_json(&self) -> Value {
+        let mut node = json!({"passing": self.passing, "total": self.total});
+        if let Some(action) = self.action {
+            node["move"] = action.to_string().into();
+            let after = action.board(self.position.board);
+            let cells: Vec<u32> = (0..40).filter(|idx| (after.0 & !self.position.board.0) & (1 << idx) != 0).collect();
+            node["cells"] = cells.into();
+        }
+        let mut next = serde_json::Map::new();
+        for (shape, child) in &self.next {
+            match shape {
+                Some(shape) => {
+                    next.insert(shape.name().to_string(), child.to_json());
+                }
+                None => node["then"] = child.to_json(),
+            }
+        }
+        if !next.is_empty() {
+            node["next"] = next.into();
+        }
+        node
+    }
+
+    /// Depth first, each node before the nodes after it
+    fn visit<'a>(&'a self, path: &mut String, f: &mut impl FnMut(&str, &'a Node)) {
+        f(path, self);
+        for (shape, child) in &self.next {
+            let len = path.len();
+            path.push_str(revealed_name(*shape));
+            child.visit(path, f);
+            path.truncate(len);
+        }
+    }
+}
+
+impl Strategy {
+    pub fn chance(&self) -> f64 {
+        self.passing as f64 / self.total as f64 * 100.0
+    }
+
+    fn visit<'a>(&'a self, mut f: impl FnMut(&str, &'a Node)) {
+        for (queue, node) in &self.openings {
+            let mut path: String = queue.iter().map(|shape| shape.name()).collect();
+            path.push(' ');
+            node.visit(&mut path, &mut f);
+        }
+    }
+
+    /// Write the tree.  Text indents each node under the one before it, json
+    /// nests them, and fumen gives one page per node, depth first.
+    pub fn write(&self, format: StrategyFormat, mut to: impl Write) -> std::io::Result<()> {
+        let board = encode_boards([(self.board, None)]);
+        match format {
+            StrategyFormat::Text => {
+                writeln!(to, "passing queues: {}/{}", self.passing, self.total)?;
+                writeln!(to, "chance: {}%", self.chance())?;
+                let mut lines = Vec::new();
+                self.visit(|path, node| {
+                    let indent = 2 * path.split(' ').nth(1).map_or(0, str::len);
+                    lines.push(format!("{:indent$}{}: {}", "", queue_name(&node.position), node.describe()));
+                });
+                for line in lines {
+                    writeln!(to, "{line}")?;
+                }
+            }
+            StrategyFormat::Json => {
+                let openings: serde_json::Map<String, Value> = self
+                    .openings
+                    .iter()
+                    .map(|(queue, node)| (queue.iter().map(|shape| shape.name()).collect(), node.to_json()))
+                    .collect();
+                serde_json::to_writer(&mut to, &json!({
+                    "inputs": {
+                        "board": board,
+                        "pattern": self.pattern,
+                        "previews": self.previews,
+                        "init_hold": self.init_hold,
+                        "use_hold": self.use_hold,
+                        "culled": self.culled,
+                        "two_line": self.two_line,
+                    },
+                    "passing": self.passing,
+                    "total": self.total,
+                    "chance": self.chance(),
+                    "openings": openings,
+                }))?;
+                writeln!(to)?;
+            }
+            StrategyFormat::Fumen => {
+                let mut pages = Vec::new();
+                self.visit(|path, node| {
+                    let placed = node
+                        .action
+                        .and_then(|action| Some((action.placed()?, action.board(node.position.board))));
+                    let comment = format!("{path} {}: {}", queue_name(&node.position), node.describe());
+                    pages.push((node.position.board, placed, Some(comment)));
+                });
+                writeln!(to, "{}", encode_placements(pages))?;
+            }
+        }
+        to.flush()
+    }
+}
+
+#[test]
+fn follows_the_chance() {
+    use std::str::FromStr;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let queue = CombinatoricQueue::from_str("O,*p6").unwrap();
+
+    for use_hold in [true, false] {
+        let strategy = limited_see_strategy(&gigapan, board, &queue, &ChanceOptions { previews: 1, init_hold: true, use_hold, ..ChanceOptions::default() }, None).unwrap();
+        let chance = crate::calculate::limited_see_chance(&gigapan, board, &queue, 1, true, use_hold, false, false).unwrap();
+        assert_eq!(strategy.openings.len(), 42);
+        assert_eq!((strategy.passing, strategy.total), (chance.passing, chance.total));
+
+        // each move passes what the best moves after it pass
+        strategy.visit(|_, node| {
+            if node.action.is_some() {
+                assert_eq!(node.passing, node.next.iter().map(|(_, child)| child.passing).sum::<usize>());
+            }
+        });
+    }
+
+    let shallow = limited_see_strategy(&gigapan, board, &queue, &ChanceOptions { previews: 1, init_hold: true, use_hold: true, ..ChanceOptions::default() }, Some(1)).unwrap();
+    assert!(shallow.openings.iter().all(|(_, node)| node.next.is_empty()));
+}
@@ -0,0 +1,160 @@
+//! The limited see chance over a range of preview counts, with and without
+//! hold, in one run so the graph, culled boards and search results are shared.
+
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use legal_boards::boardgraph::FrozenGigapan;
+use srs_4l::gameplay::Board;
+
+use crate::calculate::{culled_boards, fills_board, limited_see_chance_with, ChanceOptions, ChanceResult, LimitedSeeMemo, OutputFormat};
+use crate::fumens::encode_boards;
+use crate::queue::CombinatoricQueue;
+
+/// The chance for every preview count and hold setting that was run
+pub struct Sweep {
+    pub board: Board,
+    pub pattern: String,
+    pub init_hold: bool,
+    pub culled: bool,
+    pub two_line: bool,
+    /// By hold setting, then by previews
+    pub results: Vec<ChanceResult>,
+    pub elapsed: Duration,
+}
+
+/// Run [`limited_see_chance`](crate::calculate::limited_see_chance) for each
+/// of `previews` and each of `holds`, in place of the previews and hold of
+/// `options`.  Preview counts which would reveal more than the whole queue are
+/// left out.
+pub fn limited_see_sweep(
+    gigapan: &FrozenGigapan,
+    board: Board,
+    combinatoric_queue: &CombinatoricQueue,
+    previews: RangeInclusive<usize>,
+    holds: &[bool],
+    options: &ChanceOptions,
+) -> Option<Sweep> {
+    let instant = Instant::now();
+    if !fills_board(board, combinatoric_queue) {
+        eprintln!("bad queue len");
+        return None;
+    }
+    let init_hold = options.init_hold || combinatoric_queue.hold().is_some();
+    let most = combinatoric_queue.get_counted_bags().len() - 1 - init_hold as usize;
+    if *previews.end() > most {
+        eprintln!("the queue only allows up to {most} previews");
+    }
+    let previews = *previews.start()..=(*previews.end()).min(most);
+
+    let mut results = Vec::new();
+    for &use_hold in holds {
+        let culled = options.culled.then(|| culled_boards(gigapan, board, combinatoric_queue, use_hold));
+        // the search doesn't depend on how many pieces were seen at the start
+        let memo = LimitedSeeMemo::new();
+        for previews in previews.clone() {
+            eprintln!("previews: {previews} hold: {use_hold}");
+            // a checkpoint is of a single run
+            let options = ChanceOptions { previews, init_hold, use_hold, checkpoint: None, ..*options };
+            let result = limited_see_chance_with(gigapan, board, combinatoric_queue, &options, culled.as_ref(), &memo);
+            results.push(result);
+        }
+    }
+
+    Some(Sweep {
+        board,
+        pattern: combinatoric_queue.to_string(),
+        init_hold,
+        culled: options.culled,
+        two_line: options.two_line,
+        results,
+        elapsed: instant.elapsed(),
+    })
+}
+
+impl Sweep {
+    /// Write one row per run
+    pub fn write(&self, format: OutputFormat, mut to: impl Write) -> std::io::Result<()> {
+        let board = encode_boards([(self.board, None)]);
+        let seconds = self.elapsed.as_secs_f64();
+
+        match format {
+            OutputFormat::Text => {
+                writeln!(to, "{:>8} {:>5} {:>21} {:>8}", "previews", "hold", "passing", "chance")?;
+                for result in &self.results {
+                    let passing = format!("{}/{}", result.passing, result.total);
+                    writeln!(to, "{:>8} {:>5} {:>21} {:>7.2}%", result.previews, result.use_hold, passing, result.chance())?;
+                }
+            }
+            OutputFormat::Json => {
+                let runs: Vec<serde_json::Value> = self
+                    .results
+                    .iter()
+                    .map(|result| {
+                        let (numerator, denominator) = result.fraction();
+                        serde_json::json!({
+                            "previews": result.previews,
+                            "use_hold": result.use_hold,
+                            "passing": result.passing,
+                            "total": result.total,
+                            "fraction": [numerator, denominator],
+                            "chance": result.chance(),
+                            "seconds": result.elapsed.as_secs_f64(),
+                        })
+                    })
+                    .collect();
+                serde_json::to_writer(&mut to, &serde_json::json!({
+                    "inputs": {
+                        "board": board,
+                        "pattern": self.pattern,
+                        "init_hold": self.init_hold,
+                        "culled": self.culled,
+                        "two_line": self.two_line,
+                    },
+                    "seconds": seconds,
+                    "runs": runs,
+                }))?;
+                writeln!(to)?;
+            }
+            OutputFormat::Csv => {
+                writeln!(to, "# board: {board}")?;
+                writeln!(to, "# pattern: {}", self.pattern)?;
+                writeln!(to, "# init_hold: {}", self.init_hold)?;
+                writeln!(to, "# culled: {}", self.culled)?;
+                writeln!(to, "# two_line: {}", self.two_line)?;
+                writeln!(to, "# seconds: {seconds}")?;
+                writeln!(to, "previews,use_hold,passing,total,chance,seconds")?;
+                for result in &self.results {
+                    writeln!(
+                        to,
+                        "{},{},{},{},{},{}",
+                        result.previews,
+                        result.use_hold,
+                        result.passing,
+                        result.total,
+                        result.chance(),
+                        result.elapsed.as_secs_f64()
+                    )?;
+                }
+            }
+        }
+        to.flush()
+    }
+}
+
+#[test]
+fn matches_single_runs() {
+    use std::str::FromStr;
+
+    let (board, gigapan) = crate::fixture::small_board();
+    let queue = CombinatoricQueue::from_str("O,*p6").unwrap();
+
+    let sweep = limited_see_sweep(&gigapan, board, &queue, 0..=2, &[true, false], &ChanceOptions { init_hold: true, ..ChanceOptions::default() }).unwrap();
+    assert_eq!(sweep.results.len(), 2 * 3);
+    for result in &sweep.results {
+        let single = crate::calculate::limited_see_chance(&gigapan, board, &queue, result.previews, true, result.use_hold, false, false).unwrap();
+        assert_eq!((result.passing, result.total), (single.passing, single.total));
+        assert_eq!(result.prefixes, single.prefixes);
+    }
+}
@@ -0,0 +1,283 @@
+//! Integrity checks for gigapan data read back from disk.
+
+use hashbrown::HashSet;
+use legal_boards::boardgraph::{compute_from, FrozenGigapan};
+use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use srs_4l::gameplay::{Board, Shape};
+use srs_4l::vector::Placements;
+
+use crate::fumens::encode_boards;
+use crate::stats::count_paths;
+
+const FULL: Board = Board(0xFFFFF_FFFFF);
+
+/// Only nodes this close to a perfect clear are recomputed, since
+/// regenerating from a shallow node rebuilds most of the graph.
+pub const SAMPLE_PIECES_LEFT: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchKind {
+    /// The child can't be made by placing the shape on the parent
+    UnreplayableEdge,
+    /// The child is neither a node nor a perfect clear
+    MissingChild,
+    /// The node has no path to a perfect clear
+    NoPathToFull,
+    /// Recomputation found a child which is not in the graph
+    MissingEdge,
+    /// The graph has a child which recomputation didn't find
+    ExtraEdge,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub kind: MismatchKind,
+    pub board: Board,
+    pub edge: Option<(Shape, Board)>,
+}
+
+impl Mismatch {
+    /// A fumen of the board, followed by the child if there is one
+    pub fn fumen(&self) -> String {
+        let mut pages = vec![(self.board, Some(format!("{:?}", self.kind)))];
+        if let Some((shape, child)) = self.edge {
+            pages.push((child, Some(format!("after {}", shape.name()))));
+        }
+        encode_boards(pages)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub nodes: usize,
+    pub edges: usize,
+    pub sampled: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    pub fn print(&self, limit: usize) {
+        println!(
+            "checked {} nodes, {} edges, recomputed {} nodes",
+            self.nodes, self.edges, self.sampled
+        );
+        for kind in [
+            MismatchKind::UnreplayableEdge,
+            MismatchKind::MissingChild,
+            MismatchKind::NoPathToFull,
+            MismatchKind::MissingEdge,
+            MismatchKind::ExtraEdge,
+        ] {
+            let found: Vec<&Mismatch> = self.mismatches.iter().filter(|m| m.kind == kind).collect();
+            println!("{:?}: {}", kind, found.len());
+            for mismatch in found.iter().take(limit) {
+                println!("    {}", mismatch.fumen());
+            }
+        }
+        if self.is_ok() {
+            println!("gigapan ok");
+        }
+    }
+}
+
+/// Check that every edge replays, every child exists, every node can reach a
+/// perfect clear, and that the edges of `sample` random nodes at most
+/// [`SAMPLE_PIECES_LEFT`] pieces from a perfect clear match a fresh
+/// recomputation with [`compute_from`].
+pub fn verify(gigapan: &FrozenGigapan, sample: usize, seed: u64) -> VerifyReport {
+    let mut report = VerifyReport {
+        nodes: gigapan.len(),
+        ..Default::default()
+    };
+
+    let (edges, mut mismatches) = gigapan
+        .par_iter()
+        .map(|(&board, edges)| check_edges(gigapan, board, edges))
+        .reduce(
+            || (0, Vec::new()),
+            |(a_count, mut a), (b_count, b)| {
+                a.extend(b);
+                (a_count + b_count, a)
+            },
+        );
+    report.edges = edges;
+
+    let mut dead: Vec<Board> = count_paths(gigapan)
+        .into_iter()
+        .filter(|&(_, paths)| paths == 0)
+        .map(|(board, _)| board)
+        .collect();
+    dead.sort_unstable();
+    mismatches.extend(dead.into_iter().map(|board| Mismatch {
+        kind: MismatchKind::NoPathToFull,
+        board,
+        edge: None,
+    }));
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut sampled: Vec<Board> = gigapan
+        .iter()
+        .map(|(&board, _)| board)
+        .filter(|board| board.0.count_ones() >= 40 - 4 * SAMPLE_PIECES_LEFT)
+        .choose_multiple(&mut rng, sample);
+    sampled.sort_unstable();
+    report.sampled = sampled.len();
+
+    // Every child of the sampled nodes which can reach a perfect clear, found
+    // by generating again from them
+    let placed: Vec<Board> = sampled
+        .iter()
+        .flat_map(|&board| Shape::ALL.into_iter().flat_map(move |shape| Placements::place(board, shape).canonical()))
+        .map(|(_, child)| child)
+        .collect();
+    let reaches_full: HashSet<Board> = compute_from(&placed).into_iter().collect();
+
+    for board in sampled {
+        let edges = gigapan.get(&board).unwrap();
+        for shape in Shape::ALL {
+            let stored: HashSet<Board> = edges[shape as usize].iter().copied().collect();
+            let fresh: HashSet<Board> = Placements::place(board, shape)
+                .canonical()
+                .map(|(_, child)| child)
+                .filter(|child| reaches_full.contains(child))
+                .collect();
+
+            for &child in fresh.difference(&stored) {
+                mismatches.push(Mismatch {
+                    kind: MismatchKind::MissingEdge,
+                    board,
+                    edge: Some((shape, child)),
+                });
+            }
+            for &child in stored.difference(&fresh) {
+                mismatches.push(Mismatch {
+                    kind: MismatchKind::ExtraEdge,
+                    board,
+                    edge: Some((shape, child)),
+                });
+            }
+        }
+    }
+
+    report.mismatches = mismatches;
+    report
+}
+
+fn check_edges(gigapan: &FrozenGigapan, board: Board, edges: &[Vec<Board>; 7]) -> (usize, Vec<Mismatch>) {
+    let mut count = 0;
+    let mut mismatches = Vec::new();
+
+    for shape in Shape::ALL {
+        let children = &edges[shape as usize];
+        if children.is_empty() {
+            continue;
+        }
+        count += children.len();
+
+        let placed: HashSet<Board> = Placements::place(board, shape)
+            .canonical()
+            .map(|(_, child)| child)
+            .collect();
+
+        for &child in children {
+            let kind = if !placed.contains(&child) {
+                MismatchKind::UnreplayableEdge
+            } else if child != FULL && gigapan.get(&child).is_none() {
+                MismatchKind::MissingChild
+            } else {
+                continue;
+            };
+            mismatches.push(Mismatch {
+                kind,
+                board,
+                edge: Some((shape, child)),
+            });
+        }
+    }
+
+    (count, mismatches)
+}
+
+#[test]
+fn corrupted() {
+    use legal_boards::boardgraph::Gigapan;
+
+    type Node = (Board, [Vec<Board>; 7]);
+    fn position(nodes: &[Node], found: impl Fn(&Board, &[Vec<Board>; 7]) -> bool) -> usize {
+        nodes.iter().position(|(board, edges)| found(board, edges)).unwrap()
+    }
+
+    let (root, gigapan) = crate::fixture::small_board();
+    assert!(verify(&gigapan, gigapan.len(), 0).is_ok());
+
+    let mut nodes: Vec<Node> = gigapan.iter().map(|(&board, edges)| (board, edges.clone())).collect();
+    nodes.sort_unstable_by_key(|&(board, _)| board);
+
+    // a node one piece from the end, and one of its parents
+    let dropped = nodes[position(&nodes, |board, _| board.0.count_ones() == 36)].0;
+    let parent = nodes[position(&nodes, |_, edges| edges.iter().flatten().any(|&child| child == dropped))].0;
+    nodes.retain(|&(board, _)| board != dropped);
+
+    // an O can't clear the small board in one placement
+    let idx = position(&nodes, |&board, _| board == root);
+    nodes[idx].1[Shape::O as usize].push(FULL);
+
+    let idx = position(&nodes, |board, edges| {
+        board.0.count_ones() == 32 && *board != parent && edges[Shape::I as usize].len() > 1
+    });
+    let truncated = nodes[idx].0;
+    let removed = nodes[idx].1[Shape::I as usize].pop().unwrap();
+
+    let idx = position(&nodes, |board, _| {
+        board.0.count_ones() == 32 && ![parent, truncated].contains(board)
+    });
+    let emptied = nodes[idx].0;
+    nodes[idx].1 = Default::default();
+
+    // a placement which leaves no way to a perfect clear
+    let dead_edge = |board: Board, edges: &[Vec<Board>; 7]| {
+        Shape::ALL.into_iter().find_map(|shape| {
+            Placements::place(board, shape)
+                .canonical()
+                .map(|(_, child)| child)
+                .find(|child| !edges[shape as usize].contains(child))
+                .map(|child| (shape, child))
+        })
+    };
+    let idx = position(&nodes, |&board, edges| {
+        board.0.count_ones() == 32 && ![parent, truncated, emptied].contains(&board) && dead_edge(board, edges).is_some()
+    });
+    let extra = nodes[idx].0;
+    let (shape, dead_child) = dead_edge(extra, &nodes[idx].1).unwrap();
+    nodes[idx].1[shape as usize].push(dead_child);
+
+    let corrupted = Gigapan::new();
+    for (board, edges) in nodes {
+        corrupted.insert(board, edges);
+    }
+    let corrupted = corrupted.freeze();
+    let report = verify(&corrupted, corrupted.len(), 0);
+
+    let found = |kind: MismatchKind, board: Board| {
+        let mismatch = report
+            .mismatches
+            .iter()
+            .find(|mismatch| mismatch.kind == kind && mismatch.board == board)
+            .unwrap_or_else(|| panic!("no {kind:?} for {board}"));
+        let fumen = mismatch.fumen();
+        assert_eq!(crate::fumens::decode_fumen(&fumen), Some(board.0), "{fumen}");
+        let pages = fumen::Fumen::decode(&fumen).unwrap().pages.len();
+        assert_eq!(pages, 1 + mismatch.edge.is_some() as usize, "{fumen}");
+        mismatch.edge
+    };
+    assert_eq!(found(MismatchKind::MissingChild, parent).map(|(_, child)| child), Some(dropped));
+    assert_eq!(found(MismatchKind::UnreplayableEdge, root), Some((Shape::O, FULL)));
+    assert_eq!(found(MismatchKind::MissingEdge, truncated), Some((Shape::I, removed)));
+    assert_eq!(found(MismatchKind::NoPathToFull, emptied), None);
+    assert_eq!(found(MismatchKind::ExtraEdge, extra), Some((shape, dead_child)));
+}
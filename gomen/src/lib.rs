@@ -17,6 +17,9 @@ pub mod solver;
 #[wasm_bindgen]
 pub struct Solver {
     boards: HashSet<Board>,
+    /// The legal boards of the last garbage start added which isn't in the
+    /// list, kept apart so they don't pile up as the garbage changes
+    start_boards: HashSet<Board>,
 }
 
 #[wasm_bindgen]
@@ -31,7 +34,10 @@ impl Solver {
             None => Default::default(),
         };
 
-        Solver { boards }
+        Solver {
+            boards,
+            start_boards: Default::default(),
+        }
     }
 
     pub fn solve(&self, queue: Queue, garbage: u64, can_hold: bool) -> String {
@@ -39,8 +45,10 @@ impl Solver {
 
         let start = BrokenBoard::from_garbage(garbage);
 
-        let legal_boards = if self.is_fast(garbage) {
+        let legal_boards = if solver::is_legal(&self.boards, start.board) {
             &self.boards
+        } else if self.start_boards.contains(&start.board) {
+            &self.start_boards
         } else {
             &empty_boards
         };
@@ -60,8 +68,21 @@ impl Solver {
     }
 
     pub fn is_fast(&self, garbage: u64) -> bool {
-        self.boards
-            .contains(&BrokenBoard::from_garbage(garbage).board)
+        let start = BrokenBoard::from_garbage(garbage).board;
+        solver::is_legal(&self.boards, start) || self.start_boards.contains(&start)
+    }
+
+    /// Find the legal boards reachable from this garbage, so that solving from
+    /// it takes the fast path.  They replace those of the last garbage added.
+    /// Returns whether the garbage is now fast; it won't be if no perfect
+    /// clear is possible from it.
+    pub fn add_start(&mut self, garbage: u64) -> bool {
+        if !self.is_fast(garbage) {
+            let start = BrokenBoard::from_garbage(garbage).board;
+            self.start_boards = solver::legal_boards(start);
+        }
+
+        self.is_fast(garbage)
     }
 }
 
@@ -104,11 +125,17 @@ fn parse_shape(shape: char) -> Option<Shape> {
     }
 }
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     pub fn progress(piece_count: usize, stage: usize, board_idx: usize, board_total: usize);
 }
 
+/// There's no page to report progress to outside the browser, which is only
+/// the case in the tests.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn progress(_piece_count: usize, _stage: usize, _board_idx: usize, _board_total: usize) {}
+
 #[wasm_bindgen]
 pub fn solution_info(encoded: &str) -> String {
     let mut ret = "".to_string();
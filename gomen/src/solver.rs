@@ -6,6 +6,7 @@ use smallvec::SmallVec;
 
 use srs_4l::{
     brokenboard::BrokenBoard,
+    feasibility::{Feasibility, ShapeCounts},
     gameplay::{Board, Shape},
     vector::Placements,
 };
@@ -14,6 +15,44 @@ use crate::queue::{Bag, QueueState};
 
 type ScanStage = HashMap<Board, (SmallVec<[QueueState; 7]>, SmallVec<[Board; 6]>)>;
 
+/// Whether a board is in the legal board list.  The list may be mirror
+/// reduced, so the mirror is checked too.  This can only let through extra
+/// boards, which are culled later anyway.
+pub fn is_legal(legal_boards: &HashSet<Board>, board: Board) -> bool {
+    legal_boards.contains(&board) || legal_boards.contains(&board.mirror())
+}
+
+/// The most of each shape which could still be placed on `board`, once
+/// `taken` pieces of `bags[bag_idx]` have been drawn leaving `queue`.  Pieces
+/// the board needs after the last bag could be anything.
+fn remaining_shapes(bags: &[Bag], bag_idx: usize, taken: u8, queue: QueueState, board: Board) -> ShapeCounts {
+    let bag = &bags[bag_idx];
+    let mut counts = ShapeCounts::default();
+    let mut pieces = (bag.count - taken) as u32;
+
+    if let Some(hold) = queue.hold() {
+        counts.add(hold, 1);
+        pieces += 1;
+    }
+    for shape in Shape::ALL {
+        let left = (queue.0 & bag.masks[shape as usize]).count_ones() as u8;
+        counts.add(shape, left.min(bag.count - taken));
+    }
+    for later in &bags[bag_idx + 1..] {
+        for shape in Shape::ALL {
+            counts.add(shape, (later.masks[shape as usize].count_ones() as u8).min(later.count));
+        }
+        pieces += later.count as u32;
+    }
+
+    let needed = (40 - board.0.count_ones()) / 4;
+    let unknown = needed.saturating_sub(pieces).min(u8::MAX as u32) as u8;
+    for shape in Shape::ALL {
+        counts.add(shape, unknown);
+    }
+    counts
+}
+
 fn scan(
     legal_boards: &HashSet<Board>,
     start: Board,
@@ -23,13 +62,15 @@ fn scan(
     place_last: bool,
 ) -> Vec<ScanStage> {
     let mut stages = Vec::new();
+    let mut feasibility = Feasibility::new();
 
     let mut prev: ScanStage = HashMap::new();
     prev.insert(start, (bags.first().unwrap().init_hold(), SmallVec::new()));
 
-    for (stage, (bag, i)) in bags
+    for (stage, (bag_idx, bag, i)) in bags
         .iter()
-        .flat_map(|b| (0..b.count).into_iter().map(move |i| (b, i)))
+        .enumerate()
+        .flat_map(|(bag_idx, b)| (0..b.count).into_iter().map(move |i| (bag_idx, b, i)))
         .skip(1)
         .enumerate()
     {
@@ -49,7 +90,24 @@ fn scan(
                 }
 
                 for (_, new_board) in Placements::place(old_board, shape).canonical() {
-                    if !legal_boards.is_empty() && !legal_boards.contains(&new_board) {
+                    if legal_boards.is_empty() {
+                        if new_board.is_dead() {
+                            continue;
+                        }
+                    } else if !is_legal(legal_boards, new_board) {
+                        continue;
+                    }
+
+                    // drop the queues whose pieces can't fill the board
+                    let new_queues: SmallVec<[QueueState; 7]> = new_queues
+                        .iter()
+                        .copied()
+                        .filter(|&queue| {
+                            let available = remaining_shapes(bags, bag_idx, i + 1, queue, new_board);
+                            feasibility.can_tile(new_board, available)
+                        })
+                        .collect();
+                    if new_queues.is_empty() {
                         continue;
                     }
 
@@ -81,7 +139,11 @@ fn scan(
             for shape in Shape::ALL {
                 if old_queues.iter().any(|queue| queue.hold() == Some(shape)) {
                     for (_, new_board) in Placements::place(old_board, shape).canonical() {
-                        if !legal_boards.is_empty() && !legal_boards.contains(&new_board) {
+                        if legal_boards.is_empty() {
+                            if new_board.is_dead() {
+                                continue;
+                            }
+                        } else if !is_legal(legal_boards, new_board) {
                             continue;
                         }
 
@@ -241,6 +303,57 @@ pub fn compute(
     solutions
 }
 
+/// Compute the set of boards reachable from `start` which can still reach a
+/// perfect clear, in the same form as the precomputed legal board list.  This
+/// makes garbage starts that aren't in the list fast as well.
+///
+/// This is a single threaded version of `legal_boards::boardgraph::compute_from`,
+/// which can't run here, generating children the same way with
+/// [`Board::live_children`].  The tests check that the two agree.
+pub fn legal_boards(start: Board) -> HashSet<Board> {
+    const FULL: Board = Board(0xFFFFF_FFFFF);
+
+    let mut legal = HashSet::new();
+    if start.0.count_ones() % 4 != 0 {
+        return legal;
+    }
+
+    let mut stages: Vec<HashMap<Board, SmallVec<[Board; 6]>>> = Vec::new();
+    let mut prev = HashMap::new();
+    prev.insert(start, SmallVec::new());
+
+    while !prev.contains_key(&FULL) && !prev.is_empty() {
+        let mut next: HashMap<Board, SmallVec<[Board; 6]>> = HashMap::new();
+
+        for &old_board in prev.keys() {
+            for (_, new_board) in old_board.live_children() {
+                let preds = next.entry(new_board).or_default();
+                if !preds.contains(&old_board) {
+                    preds.push(old_board);
+                }
+            }
+        }
+
+        stages.push(prev);
+        prev = next;
+    }
+
+    if prev.contains_key(&FULL) {
+        legal.insert(FULL);
+        legal.extend(&prev[&FULL]);
+    }
+
+    for stage in stages.iter().rev() {
+        for (&board, preds) in stage.iter() {
+            if legal.contains(&board) {
+                legal.extend(preds);
+            }
+        }
+    }
+
+    legal
+}
+
 pub fn print(board: &BrokenBoard, to: &mut String) {
     let pieces: Vec<(Shape, Board)> = board
         .pieces
@@ -266,3 +379,36 @@ pub fn print(board: &BrokenBoard, to: &mut String) {
         }
     }
 }
+
+#[test]
+fn legal_boards_match_compute_from() {
+    let starts = [
+        Board(0b1111111100_1111111100),
+        Board(0b1111001111_1110011111_1100111111),
+        // can never be filled
+        Board(0b0111111111),
+    ];
+    for start in starts {
+        let mut boards: Vec<Board> = legal_boards(start).into_iter().collect();
+        boards.sort_unstable();
+        assert_eq!(boards, ::legal_boards::boardgraph::compute_from(&[start]));
+    }
+}
+
+#[test]
+fn scan_cuts_unfillable_queues() {
+    use Shape::*;
+
+    let start = Board(0b1111111100_1111111100);
+    let bags: Vec<Bag> = [O, I, I, I, J, L].iter().map(|&shape| Bag::new(&[shape], 1)).collect();
+    let stages = scan(&HashSet::new(), start, &bags, bags.len(), false, true);
+
+    // The O on top of the garbage leaves gaps of two and four columns, which
+    // the rest of the queue can't tile, though other pieces could.
+    let cut = Board(start.0 | 0b0011000000_0011000000 << 20);
+    assert!(start.live_children().any(|(shape, board)| shape == O && board == cut));
+    assert!(!stages[1].contains_key(&cut));
+    assert!(!stages[1].is_empty());
+
+    assert!(stages.last().unwrap().contains_key(&Board(0xFFFFF_FFFFF)));
+}
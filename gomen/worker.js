@@ -30,6 +30,11 @@ async function main() {
             postMessage({ kind: "fast", query });
         } else {
             postMessage({ kind: "slow", query });
+            // Finding the legal boards of a new garbage start takes a while,
+            // but then it and any more queries from it are fast.
+            if (legal_boards && solver.add_start(query.garbage)) {
+                postMessage({ kind: "fast", query });
+            }
         }
 
         let queue = new wasm_bindgen.Queue();
@@ -7,28 +7,63 @@ use rayon::{
 use smallvec::SmallVec;
 
 use compute::{Counter, ShardedHashMap, FrozenMap};
-use srs_4l::{
-    gameplay::{Board, Shape},
-    vector::Placements,
-};
+use srs_4l::gameplay::{Board, Shape};
 
 
 type NoHashBuilder = nohash::BuildNoHashHasher<u64>;
-type Map = ShardedHashMap<Board, SmallVec<[Board; 6]>, 20, NoHashBuilder>;
-type GraphMap = ShardedHashMap<Board, SmallVec<[(Board, Shape); 6]>, 20, NoHashBuilder>;
+type Map<const SHARD_SIZE: usize> = ShardedHashMap<Board, SmallVec<[Board; 6]>, SHARD_SIZE, NoHashBuilder>;
+type GraphMap<const SHARD_SIZE: usize> =
+    ShardedHashMap<Board, SmallVec<[(Board, Shape); 6]>, SHARD_SIZE, NoHashBuilder>;
 
 pub type Gigapan = ShardedHashMap<Board, [Vec<Board>;7], 20, NoHashBuilder>;
 pub type FrozenGigapan = FrozenMap<Board, [Vec<Board>;7], 20, NoHashBuilder>;
 
-type Set = ShardedHashMap<Board, (), 20, NoHashBuilder>;
+type Set<const SHARD_SIZE: usize> = ShardedHashMap<Board, (), SHARD_SIZE, NoHashBuilder>;
+
+const FULL: Board = Board(0xFFFFF_FFFFF);
+
+/// Which stage a board belongs to: the number of pieces it takes to fill its
+/// minoes.  Boards whose mino count is not a multiple of 4 can never become a
+/// perfect clear, so they have no stage.
+fn stage_of(board: Board) -> Option<usize> {
+    let minoes = board.0.count_ones() as usize;
+    if minoes % 4 == 0 {
+        Some(minoes / 4)
+    } else {
+        None
+    }
+}
 
 pub fn compute() -> Vec<Board> {
-    let mut stages: Vec<Map> = Vec::new();
+    compute_boards_from::<20>(&[Board::empty()])
+}
+
+/// Compute every board which is reachable from one of `starts` and from which a
+/// perfect clear is still reachable.  The start boards themselves are included
+/// if they can reach a perfect clear.
+///
+/// Start boards can be at different heights.  Start boards whose mino count is
+/// not a multiple of 4 are ignored, since they can never be filled.  Like
+/// [`subgraph`], this is sized for boards with garbage rather than the whole
+/// list.
+pub fn compute_from(starts: &[Board]) -> Vec<Board> {
+    compute_boards_from::<12>(starts)
+}
+
+/// The actual legal board computation, with `1 << SHARD_SIZE` shards per stage
+fn compute_boards_from<const SHARD_SIZE: usize>(starts: &[Board]) -> Vec<Board> {
+    let mut stages: Vec<Map<SHARD_SIZE>> = Vec::new();
     stages.resize_with(11, Map::new);
 
-    stages[0].insert(Board::empty(), SmallVec::new());
+    let mut first_stage = 10;
+    for &start in starts {
+        if let Some(stage) = stage_of(start) {
+            stages[stage].insert(start, SmallVec::new());
+            first_stage = first_stage.min(stage);
+        }
+    }
 
-    for iter in 1..=10 {
+    for iter in first_stage + 1..=10 {
         let (prev_stage, this_stage) = match &mut stages[iter - 1..] {
             [prev, this, ..] => (prev, this),
             _ => unreachable!(),
@@ -54,17 +89,11 @@ pub fn compute() -> Vec<Board> {
             });
 
             prev_stage.par_iter_mut().for_each(|(&board, _preds)| {
-                for shape in Shape::ALL {
-                    for (_piece, new_board) in Placements::place(board, shape).canonical() {
-                        if new_board.has_isolated_cell() || new_board.has_imbalanced_split() {
-                            continue;
-                        }
-
-                        let mut guard = this_stage.get_shard_guard(&new_board);
-                        let preds = guard.entry(new_board).or_default();
-                        if !preds.contains(&board) {
-                            preds.push(board);
-                        }
+                for (_shape, new_board) in board.live_children() {
+                    let mut guard = this_stage.get_shard_guard(&new_board);
+                    let preds = guard.entry(new_board).or_default();
+                    if !preds.contains(&board) {
+                        preds.push(board);
                     }
                 }
                 counter.increment();
@@ -77,16 +106,17 @@ pub fn compute() -> Vec<Board> {
 
     let stages: Vec<_> = stages.drain(..).map(ShardedHashMap::freeze).collect();
 
-    const FULL: Board = Board(0xFFFFF_FFFFF);
     let mut work = {
-        let work = Set::new();
-        work.insert(FULL, ());
+        let work = Set::<SHARD_SIZE>::new();
+        if stages[10].get(&FULL).is_some() {
+            work.insert(FULL, ());
+        }
         work.freeze()
     };
-    let mut all_boards = vec![FULL];
+    let mut all_boards: Vec<Board> = work.iter().map(|(&board, ())| board).collect();
 
     for (i, stage) in stages.iter().enumerate().rev() {
-        println!("{:>4}-piece boards: {:>9}", i, work.len());
+        eprintln!("{:>4}-piece boards: {:>9}", i, work.len());
 
         work = work
             .par_iter()
@@ -100,20 +130,215 @@ pub fn compute() -> Vec<Board> {
     // Dropping the stages takes a long time.  We're almost done anyway.
     std::mem::forget(stages);
 
-    println!("sorting...");
+    eprintln!("sorting...");
     all_boards.par_sort_unstable();
-    println!("sorted.");
+    eprintln!("sorted.");
     all_boards
 }
 
 
-pub fn compute_gigapan() -> (Gigapan, Gigapan){
-    let mut stages: Vec<GraphMap> = Vec::new();
+pub fn compute_gigapan() -> Gigapan {
+    compute_gigapan_from::<20>(&[Board::empty()])
+}
+
+/// Compute the graph of every board reachable from one of `starts` which can
+/// still reach a perfect clear, along with the placements between them.
+///
+/// This is much cheaper than [`compute_gigapan`] for boards with garbage, and
+/// is suitable for building a graph on demand.  See [`compute_from`] for how
+/// start boards are handled.
+pub fn subgraph(starts: &[Board]) -> Gigapan {
+    compute_gigapan_from::<12>(starts)
+}
+
+/// Merge the graph of `other` into `gigapan`.  Boards present in both graphs
+/// always have the same edges, so nothing is lost.
+pub fn merge(gigapan: FrozenGigapan, other: Gigapan) -> FrozenGigapan {
+    let gigapan = gigapan.thaw();
+    for (board, edges) in other.into_iter() {
+        gigapan.insert(board, edges);
+    }
+    gigapan.freeze()
+}
+
+/// Key which marks a graph as mirror reduced.  It is never a real board, since
+/// it has cells above the bottom four rows.
+const MIRROR_REDUCED: Board = Board(u64::MAX);
+
+/// Keep only one of each legal board and its mirror, preferring the smaller.
+///
+/// Placements are not quite symmetric (the kick tables aren't), so a board
+/// is only dropped when its mirror is also legal.  This means looking up the
+/// mirror of a kept board can give false positives, which is fine for a
+/// filter.
+pub fn mirror_reduce_boards(mut boards: Vec<Board>) -> Vec<Board> {
+    boards.par_sort_unstable();
+    let legal = |board: Board| boards.binary_search(&board).is_ok();
+    boards
+        .iter()
+        .copied()
+        .filter(|&board| board <= board.mirror() || !legal(board.mirror()))
+        .collect()
+}
+
+/// Store only one of each board and its mirror, preferring the smaller.
+/// Lookups through [`GigapanLookup`] mirror the edges of the stored board back.
+///
+/// Placements are not quite symmetric (the kick tables aren't), so a board is
+/// only dropped when its mirror is stored with exactly the mirrored edges.
+/// When a board is in the graph but its mirror isn't, the mirror is stored
+/// with no edges at all so that lookups don't find the board instead.
+pub fn mirror_reduce(gigapan: FrozenGigapan) -> Gigapan {
+    if gigapan.is_mirror_reduced() {
+        return gigapan.thaw();
+    }
+
+    let reduced: Gigapan = gigapan
+        .par_iter()
+        .flat_map_iter(|(&board, edges)| {
+            let mirror = board.mirror();
+            let mirror_edges = gigapan.get(&mirror);
+
+            let keep = match mirror_edges {
+                Some(mirror_edges) => board <= mirror || !mirrored_equal(edges, mirror_edges),
+                None => true,
+            };
+            let marker = match mirror_edges {
+                Some(_) => None,
+                None => Some((mirror, Default::default())),
+            };
+
+            keep.then(|| (board, edges.clone())).into_iter().chain(marker)
+        })
+        .collect();
+
+    reduced.insert(MIRROR_REDUCED, Default::default());
+    reduced
+}
+
+/// Undo [`mirror_reduce`], storing every board explicitly again.
+pub fn mirror_expand(gigapan: FrozenGigapan) -> FrozenGigapan {
+    if !gigapan.is_mirror_reduced() {
+        return gigapan;
+    }
+
+    let expanded: Gigapan = gigapan
+        .par_iter()
+        .filter(|(_board, edges)| edges.iter().any(|children| !children.is_empty()))
+        .flat_map_iter(|(&board, edges)| {
+            let mirror = board.mirror();
+            let mirrored = match gigapan.get(&mirror) {
+                None => Some((mirror, mirror_edges(edges))),
+                Some(_) => None,
+            };
+            std::iter::once((board, edges.clone())).chain(mirrored)
+        })
+        .collect();
+
+    expanded.freeze()
+}
+
+fn mirror_edges(edges: &[Vec<Board>; 7]) -> [Vec<Board>; 7] {
+    let mut mirrored: [Vec<Board>; 7] = Default::default();
+    for shape in Shape::ALL {
+        mirrored[shape.mirror() as usize] = edges[shape as usize]
+            .iter()
+            .map(|child| child.mirror())
+            .collect();
+    }
+    mirrored
+}
+
+fn mirrored_equal(edges: &[Vec<Board>; 7], mirror_edges: &[Vec<Board>; 7]) -> bool {
+    Shape::ALL.iter().copied().all(|shape| {
+        let children = &edges[shape as usize];
+        let mirror_children = &mirror_edges[shape.mirror() as usize];
+        children.len() == mirror_children.len()
+            && children
+                .iter()
+                .all(|child| mirror_children.contains(&child.mirror()))
+    })
+}
+
+/// Edge lookups which work the same whether or not a graph is mirror reduced.
+pub trait GigapanLookup {
+    /// The edges leaving `board`, if it is in the graph.
+    fn edges(&self, board: Board) -> Option<Edges<'_>>;
+
+    fn is_mirror_reduced(&self) -> bool;
+}
+
+impl GigapanLookup for FrozenGigapan {
+    fn edges(&self, board: Board) -> Option<Edges<'_>> {
+        if let Some(edges) = self.get(&board) {
+            if edges.iter().all(Vec::is_empty) {
+                // every real board has an edge, so this is a marker
+                return None;
+            }
+            return Some(Edges { edges, mirrored: false });
+        }
+
+        if self.is_mirror_reduced() {
+            let edges = self.get(&board.mirror())?;
+            return Some(Edges { edges, mirrored: true });
+        }
+
+        None
+    }
+
+    fn is_mirror_reduced(&self) -> bool {
+        self.get(&MIRROR_REDUCED).is_some()
+    }
+}
+
+/// The edges leaving one board, mirrored back if the board is stored as its
+/// mirror.
+#[derive(Clone, Copy)]
+pub struct Edges<'a> {
+    edges: &'a [Vec<Board>; 7],
+    mirrored: bool,
+}
+
+impl<'a> Edges<'a> {
+    /// The children reached by placing `shape`.
+    pub fn get(self, shape: Shape) -> impl Iterator<Item = Board> + 'a {
+        let mirrored = self.mirrored;
+        let stored_shape = if mirrored { shape.mirror() } else { shape };
+        self.edges[stored_shape as usize]
+            .iter()
+            .map(move |&child| if mirrored { child.mirror() } else { child })
+    }
+
+    /// The children of each shape, in the order of [`Shape::ALL`].
+    pub fn iter(self) -> impl Iterator<Item = (Shape, impl Iterator<Item = Board> + 'a)> {
+        Shape::ALL.iter().copied().map(move |shape| (shape, self.get(shape)))
+    }
+
+    /// The children of every shape together.
+    pub fn children(self) -> impl Iterator<Item = Board> + 'a {
+        self.iter().flat_map(|(_shape, children)| children)
+    }
+
+    pub fn contains(self, shape: Shape, child: Board) -> bool {
+        self.get(shape).any(|c| c == child)
+    }
+}
+
+/// The actual graph computation.  Intermediate stages use `1 << SHARD_SIZE`
+/// shards, so small graphs don't pay for a huge number of shards per stage.
+fn compute_gigapan_from<const SHARD_SIZE: usize>(starts: &[Board]) -> Gigapan {
+    let mut stages: Vec<GraphMap<SHARD_SIZE>> = Vec::new();
     stages.resize_with(11, GraphMap::new);
 
-    stages[0].insert(Board::empty(), SmallVec::new());
+    let mut first_stage = 10;
+    for &start in starts {
+        if let Some(stage) = stage_of(start) {
+            stages[stage].insert(start, SmallVec::new());
+            first_stage = first_stage.min(stage);
+        }
+    }
 
-    for iter in 1..=10 {
+    for iter in first_stage + 1..=10 {
         let (prev_stage, this_stage) = match &mut stages[iter - 1..] {
             [prev, this, ..] => (prev, this),
             _ => unreachable!(),
@@ -139,17 +364,11 @@ pub fn compute_gigapan() -> (Gigapan, Gigapan){
             });
 
             prev_stage.par_iter_mut().for_each(|(&board, _preds)| {
-                for shape in Shape::ALL {
-                    for (_piece, new_board) in Placements::place(board, shape).canonical() {
-                        if new_board.has_isolated_cell() || new_board.has_imbalanced_split() {
-                            continue;
-                        }
-
-                        let mut guard = this_stage.get_shard_guard(&new_board);
-                        let preds = guard.entry(new_board).or_default();
-                        if !preds.contains(&(board,shape)) {
-                            preds.push((board,shape));
-                        }
+                for (shape, new_board) in board.live_children() {
+                    let mut guard = this_stage.get_shard_guard(&new_board);
+                    let preds = guard.entry(new_board).or_default();
+                    if !preds.contains(&(board,shape)) {
+                        preds.push((board,shape));
                     }
                 }
                 counter.increment();
@@ -161,33 +380,27 @@ pub fn compute_gigapan() -> (Gigapan, Gigapan){
     }
 
     // progressively drop the stages when we are done with them
+    let full_reached = stages[10].get_mut(&FULL).is_some();
     let stages = stages.drain(..).map(ShardedHashMap::freeze);
 
-    const FULL: Board = Board(0xFFFFF_FFFFF);
-
     let graphmap = Gigapan::new();
-    let reversemap = Gigapan::new();
 
     let mut work = {
-        let work = Set::new();
-        work.insert(FULL, ());
+        let work = Set::<SHARD_SIZE>::new();
+        if full_reached {
+            work.insert(FULL, ());
+        }
         work.freeze()
     };
 
     for (i, stage) in stages.enumerate().rev() {
-        println!("{:>4}-piece boards: {:>9}", i, work.len());
+        eprintln!("{:>4}-piece boards: {:>9}", i, work.len());
 
         work = work
             .par_iter()
             .flat_map_iter(|(&board, ())| {
                 let preds = stage.get(&board).unwrap();
 
-                // let mut shard = reversemap.get_shard_guard(&board);
-                // let entry = shard.entry(board).or_insert_with(Default::default);
-                // preds.iter().for_each(|&(parent, shape)|{
-                //     entry[shape as usize].push(parent);
-                // });
-
                 preds.iter().for_each(|&(parent, shape)|{
                     let mut shard = graphmap.get_shard_guard(&parent);
                     let entry = shard.entry(parent).or_insert_with(Default::default);
@@ -201,5 +414,191 @@ pub fn compute_gigapan() -> (Gigapan, Gigapan){
             .collect();
     }
     // std::mem::forget(stages);
-    (graphmap,reversemap)
-}
\ No newline at end of file
+    graphmap
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    use srs_4l::vector::Placements;
+
+    use super::*;
+
+    /// Two rows of garbage, with the left two columns empty, which is also
+    /// the board gigapan's tests run on
+    const TWO_ROWS: Board = Board(0b1111111100_1111111100);
+    /// Three rows of garbage, each with a two wide gap one column over from
+    /// the one below
+    const THREE_ROWS: Board = Board(0b1111001111_1110011111_1100111111);
+    /// Nine minoes, which no number of pieces can fill up to a perfect clear
+    const GARBAGE: Board = Board(0b0111111111);
+
+    /// Generation as it was before `compute_from`: every placement expanded
+    /// stage by stage, culling only children with an isolated cell or an
+    /// imbalanced split, then walked back from the perfect clear
+    fn old_generation(starts: &[Board]) -> Vec<Board> {
+        let mut stages: Vec<HashMap<Board, Vec<Board>>> = vec![HashMap::new(); 11];
+        for &start in starts {
+            let minoes = start.0.count_ones() as usize;
+            if minoes % 4 == 0 {
+                stages[minoes / 4].insert(start, Vec::new());
+            }
+        }
+
+        for stage in 1..=10 {
+            let prev: Vec<Board> = stages[stage - 1].keys().copied().collect();
+            for board in prev {
+                for shape in Shape::ALL {
+                    for (_, child) in Placements::place(board, shape).canonical() {
+                        if child.has_isolated_cell() || child.has_imbalanced_split() {
+                            continue;
+                        }
+                        let preds = stages[stage].entry(child).or_default();
+                        if !preds.contains(&board) {
+                            preds.push(board);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut work: HashSet<Board> = stages[10].get(&FULL).map(|_| FULL).into_iter().collect();
+        let mut boards: Vec<Board> = work.iter().copied().collect();
+        for stage in stages.iter().rev() {
+            work = work.iter().flat_map(|board| stage[board].iter().copied()).collect();
+            boards.extend(&work);
+        }
+        boards.sort_unstable();
+        boards
+    }
+
+    /// Whether some placements lead from `board` to a perfect clear, trying
+    /// every placement without any culls
+    fn reaches_full(board: Board, memo: &mut HashMap<Board, bool>) -> bool {
+        if board == FULL {
+            return true;
+        }
+        if let Some(&known) = memo.get(&board) {
+            return known;
+        }
+        let reaches = Shape::ALL.iter().any(|&shape| {
+            Placements::place(board, shape)
+                .canonical()
+                .any(|(_, child)| reaches_full(child, memo))
+        });
+        memo.insert(board, reaches);
+        reaches
+    }
+
+    /// Every board of the graph with its children sorted, to compare graphs
+    fn sorted(gigapan: &FrozenGigapan) -> BTreeMap<Board, [Vec<Board>; 7]> {
+        gigapan
+            .iter()
+            .map(|(&board, edges)| {
+                let mut edges = edges.clone();
+                edges.iter_mut().for_each(|children| children.sort_unstable());
+                (board, edges)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn garbage_start() {
+        assert!(compute_from(&[GARBAGE]).is_empty());
+        assert_eq!(subgraph(&[GARBAGE]).len(), 0);
+        assert_eq!(compute_from(&[GARBAGE, TWO_ROWS]), compute_from(&[TWO_ROWS]));
+    }
+
+    #[test]
+    fn starts() {
+        let boards = compute_from(&[TWO_ROWS, THREE_ROWS]);
+        assert!(boards.binary_search(&TWO_ROWS).is_ok());
+        assert!(boards.binary_search(&THREE_ROWS).is_ok());
+        assert_eq!(boards, old_generation(&[TWO_ROWS, THREE_ROWS]));
+
+        let mut memo = HashMap::new();
+        assert!(boards.iter().all(|&board| reaches_full(board, &mut memo)));
+
+        // the graph has the same boards, apart from the perfect clear which
+        // has no children, and an edge for every placement between them
+        let gigapan = subgraph(&[TWO_ROWS, THREE_ROWS]).freeze();
+        let mut graph_boards: Vec<Board> = gigapan.iter().map(|(&board, _)| board).chain([FULL]).collect();
+        graph_boards.sort_unstable();
+        assert_eq!(graph_boards, boards);
+        for (&board, edges) in gigapan.iter() {
+            for shape in Shape::ALL {
+                let mut expected: Vec<Board> = Placements::place(board, shape)
+                    .canonical()
+                    .map(|(_, child)| child)
+                    .filter(|child| boards.binary_search(child).is_ok())
+                    .collect();
+                expected.sort_unstable();
+                expected.dedup();
+                let mut children = edges[shape as usize].clone();
+                children.sort_unstable();
+                assert_eq!(children, expected, "{board} {shape:?}");
+            }
+        }
+
+        // graphs built apart merge into the one built together
+        let merged = merge(subgraph(&[TWO_ROWS]).freeze(), subgraph(&[THREE_ROWS]));
+        assert_eq!(sorted(&merged), sorted(&gigapan));
+    }
+
+    /// The children of each shape, sorted
+    fn sorted_edges(edges: Edges) -> Vec<Vec<Board>> {
+        edges
+            .iter()
+            .map(|(_shape, children)| {
+                let mut children: Vec<Board> = children.collect();
+                children.sort_unstable();
+                children
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mirror_round_trip() {
+        let gigapan = subgraph(&[TWO_ROWS]).freeze();
+        let expected = sorted(&gigapan);
+
+        let reduced = mirror_reduce(gigapan).freeze();
+        assert!(reduced.is_mirror_reduced());
+        // some boards are only stored as their mirror, and the start is
+        // stored with a marker for its mirror, which isn't legal
+        assert!(expected.keys().any(|board| reduced.get(board).is_none()));
+        assert_eq!(reduced.get(&TWO_ROWS.mirror()), Some(&Default::default()));
+
+        // reducing twice changes nothing
+        let reduced = mirror_reduce(reduced).freeze();
+        assert_eq!(sorted(&mirror_expand(reduced)), expected);
+    }
+
+    #[test]
+    fn mirror_edges_lookup() {
+        let gigapan = subgraph(&[TWO_ROWS]).freeze();
+        let reduced = mirror_reduce(subgraph(&[TWO_ROWS]).freeze()).freeze();
+        assert!(!gigapan.is_mirror_reduced());
+
+        let mut through_mirror = 0;
+        for (&board, _) in gigapan.iter() {
+            for board in [board, board.mirror()] {
+                let edges = gigapan.edges(board).map(sorted_edges);
+                assert_eq!(reduced.edges(board).map(sorted_edges), edges, "{board}");
+                if edges.is_some() && reduced.get(&board).is_none() {
+                    through_mirror += 1;
+                }
+            }
+        }
+        assert!(through_mirror > 0);
+        assert!(reduced.edges(MIRROR_REDUCED).is_none());
+    }
+
+    /// Generating the whole list takes a long time, so this only runs when
+    /// asked for
+    #[test]
+    #[ignore]
+    fn empty_start() {
+        assert_eq!(compute(), old_generation(&[Board::empty()]));
+    }
+}
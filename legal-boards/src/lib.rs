@@ -8,17 +8,42 @@ use rayon::prelude::*;
 
 use boardgraph::Gigapan;
 
-pub fn create_gigapan() -> std::io::Result<()> {
-    std::fs::DirBuilder::new().recursive(true).create("./gigapan_shards")?;
+/// Generate the gigapan and write it to the directory `path`.  With `mirror`,
+/// only one of each board and its mirror is stored.
+pub fn create_gigapan(path: &str, mirror: bool) -> std::io::Result<()> {
+    std::fs::DirBuilder::new().recursive(true).create(path)?;
 
     let instant = Instant::now();
-    let (gigapan, _reversepan) = boardgraph::compute_gigapan();
+    let mut gigapan = boardgraph::compute_gigapan();
     println!("generated gigapan in {}s", instant.elapsed().as_secs());
 
-    write_pan("gigapan_shards", gigapan)?;
+    if mirror {
+        let instant = Instant::now();
+        let full_len = gigapan.len();
+        gigapan = boardgraph::mirror_reduce(gigapan.freeze());
+        println!("mirror reduced gigapan from {} to {} in {}s", full_len, gigapan.len(), instant.elapsed().as_secs());
+    }
+
+    write_pan(path, gigapan)?;
 
     Ok(())
 }
+/// Generate the list of legal boards and write it to `path`.  With `mirror`,
+/// only one of each board and its mirror is stored where possible.
+pub fn create_legal_boards(path: &str, mirror: bool) -> std::io::Result<()> {
+    let instant = Instant::now();
+    let mut boards = boardgraph::compute();
+    println!("generated {} legal boards in {}s", boards.len(), instant.elapsed().as_secs());
+
+    if mirror {
+        boards = boardgraph::mirror_reduce_boards(boards);
+        println!("mirror reduced to {} legal boards", boards.len());
+    }
+
+    let file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    srs_4l::board_list::write(&boards, BufWriter::new(file))
+}
+
 //impl Iterator<Item=(usize, impl Iterator<Item = (Board, SmallVec<[SmallVec<[Board;6]>;7]>)>)>
 fn write_pan(path:&str, mut gigapan: Gigapan) -> std::io::Result<()>{
     let instant = Instant::now();
@@ -33,7 +58,8 @@ fn write_pan(path:&str, mut gigapan: Gigapan) -> std::io::Result<()>{
         let file = OpenOptions::new()
         .write(true)
         .create(true)
-        .open(format!("./{path}/{shard}.leb128")).unwrap();
+        .truncate(true)
+        .open(std::path::Path::new(path).join(format!("{shard}.leb128")))?;
         let writer = BufWriter::new(file);
         srs_4l::board_list::write_graph(chunk, writer)?;
     }
@@ -0,0 +1,289 @@
+//! Whether a board can still be filled with the pieces which are left.
+//!
+//! Searches over a queue only find out that a board is doomed after trying
+//! every branch below it.  [`Feasibility`] answers the question directly, for
+//! a multiset of shapes, ignoring their order:  first by tiling the empty
+//! cells, and then by checking that SRS can reach enough of them with those
+//! shapes to ever clear a line.
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+use crate::gameplay::{Board, Shape, TETROMINOES};
+use crate::queue::Queue;
+use crate::vector::relaxed_coverage;
+
+const FULL: Board = Board(0xFFFFF_FFFFF);
+
+/// How many of each shape are available, indexed by `Shape as usize`.
+///
+/// These are upper bounds.  A board is feasible if it can be filled using
+/// some of the available shapes, so an extra piece in hold, or a choice of
+/// shapes from a bag, can be described by counting every shape it might be.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ShapeCounts(pub [u8; 7]);
+
+impl ShapeCounts {
+    pub fn get(self, shape: Shape) -> u8 {
+        self.0[shape as usize]
+    }
+
+    pub fn add(&mut self, shape: Shape, count: u8) {
+        self.0[shape as usize] = self.0[shape as usize].saturating_add(count);
+    }
+
+    /// These counts with one fewer of `shape`, if there is one.
+    pub fn remove(self, shape: Shape) -> Option<ShapeCounts> {
+        let mut new = self;
+        new.0[shape as usize] = self.get(shape).checked_sub(1)?;
+        Some(new)
+    }
+
+    pub fn total(self) -> u32 {
+        self.0.iter().map(|&count| count as u32).sum()
+    }
+}
+
+impl FromIterator<Shape> for ShapeCounts {
+    fn from_iter<T: IntoIterator<Item = Shape>>(iter: T) -> ShapeCounts {
+        let mut counts = ShapeCounts::default();
+        for shape in iter {
+            counts.add(shape, 1);
+        }
+        counts
+    }
+}
+
+impl From<Queue> for ShapeCounts {
+    fn from(queue: Queue) -> ShapeCounts {
+        queue.collect()
+    }
+}
+
+/// Memoized feasibility checks.  Keep one around for a whole search, since
+/// the same boards come up again and again.
+#[derive(Default)]
+pub struct Feasibility {
+    tiled: HashMap<(u64, ShapeCounts), bool>,
+    cleared: HashMap<(Board, ShapeCounts), bool>,
+}
+
+impl Feasibility {
+    pub fn new() -> Feasibility {
+        Feasibility::default()
+    }
+
+    /// Check whether `board` might be filled with the available shapes, first
+    /// by [tiling](Feasibility::can_tile) and then by
+    /// [reaching a line clear](Feasibility::can_clear_line).
+    pub fn is_feasible(&mut self, board: Board, available: ShapeCounts) -> bool {
+        self.can_tile(board, available) && self.can_clear_line(board, available)
+    }
+
+    /// Check whether the empty cells of `board` can be tiled by some of the
+    /// available shapes.
+    ///
+    /// Cells never become empty again, so every future piece lies in cells
+    /// which are empty now.  After lines are cleared, the rows of a piece may
+    /// land in rows of this board which aren't adjacent, but they stay in
+    /// order and in the same columns.  So this allows the rows of each piece
+    /// to be any increasing choice of rows.  That can only allow more tilings,
+    /// so if there is none, the board can't be filled.
+    pub fn can_tile(&mut self, board: Board, available: ShapeCounts) -> bool {
+        let empty = !board.0 & FULL.0;
+        if empty.count_ones() % 4 != 0 {
+            return false;
+        }
+        self.tile(empty, available)
+    }
+
+    fn tile(&mut self, empty: u64, available: ShapeCounts) -> bool {
+        if empty == 0 {
+            return true;
+        }
+        if available.total() < empty.count_ones() / 4 {
+            return false;
+        }
+        if let Some(&known) = self.tiled.get(&(empty, available)) {
+            return known;
+        }
+
+        // Some piece must cover the lowest empty cell, and every other cell
+        // of that piece is in a higher row or further right.  So the cell is
+        // the first of the piece's bottom row.
+        let first = empty.trailing_zeros();
+        let (row, col) = (first / 10, first % 10);
+
+        let mut result = false;
+        'pieces: for &(shape, runs) in TETROMINOES.iter() {
+            let remaining = match available.remove(shape) {
+                Some(remaining) => remaining,
+                None => continue,
+            };
+
+            let offset = runs[0].trailing_zeros();
+            if offset > col {
+                continue;
+            }
+            let start = col - offset;
+            if runs.iter().any(|run| (run << start) >> 10 != 0) {
+                continue;
+            }
+
+            let mut higher = Vec::new();
+            for rows in choose_rows(row, runs.len() - 1) {
+                let mut cells = runs[0] << (row * 10 + start);
+                for (&run, &r) in runs[1..].iter().zip(rows.iter()) {
+                    cells |= run << (r * 10 + start);
+                }
+                if cells & empty == cells {
+                    higher.push(cells);
+                }
+            }
+
+            for cells in higher {
+                if self.tile(empty & !cells, remaining) {
+                    result = true;
+                    break 'pieces;
+                }
+            }
+        }
+
+        self.tiled.insert((empty, available), result);
+        result
+    }
+
+    /// Check whether the available shapes could fill some row of `board`
+    /// completely, which the first line clear needs.
+    ///
+    /// Until a line is cleared, rows don't move, so every piece placed before
+    /// then lies in adjacent rows of cells which are empty now.  A piece also
+    /// can't cover a cell that [`relaxed_coverage`] misses for its shape, on
+    /// this board or on any board with more filled cells.  So if every row
+    /// which isn't full has an empty cell that no such piece covers, no line
+    /// is ever cleared and the board can't be filled.  A full row already
+    /// lets pieces skip over it, so then this gives up and allows the board.
+    pub fn can_clear_line(&mut self, board: Board, available: ShapeCounts) -> bool {
+        let empty = !board.0 & FULL.0;
+        if empty == 0 {
+            return true;
+        }
+        if let Some(&known) = self.cleared.get(&(board, available)) {
+            return known;
+        }
+
+        let mut reachable = [0; 7];
+        for &shape in Shape::ALL.iter() {
+            if available.get(shape) > 0 {
+                reachable[shape as usize] = empty & relaxed_coverage(board, shape).0;
+            }
+        }
+
+        let mut covered = 0;
+        for &(shape, runs) in TETROMINOES.iter() {
+            let reachable = reachable[shape as usize];
+            for row in 0..=4 - runs.len() as u32 {
+                for col in 0..10 {
+                    if runs.iter().any(|run| (run << col) >> 10 != 0) {
+                        break;
+                    }
+                    let mut cells = 0;
+                    for (r, &run) in runs.iter().enumerate() {
+                        cells |= run << ((row + r as u32) * 10 + col);
+                    }
+                    if cells & reachable == cells {
+                        covered |= cells;
+                    }
+                }
+            }
+        }
+
+        let result = (0..4).any(|row| (empty >> (row * 10)) & 0b1111111111 & !(covered >> (row * 10)) == 0);
+        self.cleared.insert((board, available), result);
+        result
+    }
+}
+
+/// Every increasing choice of `count` rows above `row`.
+fn choose_rows(row: u32, count: usize) -> Vec<Vec<u32>> {
+    if count == 0 {
+        return vec![Vec::new()];
+    }
+
+    let mut choices = Vec::new();
+    for next in row + 1..4 {
+        for mut rest in choose_rows(next, count - 1) {
+            rest.insert(0, next);
+            choices.push(rest);
+        }
+    }
+    choices
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Feasibility, ShapeCounts};
+    use crate::gameplay::{Board, Shape};
+    use crate::testing::{random_game, Random};
+
+    #[test]
+    fn two_lines() {
+        use Shape::*;
+
+        // Only the top two rows are empty.
+        let board = Board(0xFFFFF);
+        let mut feasibility = Feasibility::new();
+
+        assert!(feasibility.can_tile(board, [O, O, O, O, O].iter().copied().collect()));
+        assert!(feasibility.can_tile(board, [I, I, J, J, O].iter().copied().collect()));
+        assert!(!feasibility.can_tile(board, [I, I, I, I, I].iter().copied().collect()));
+        assert!(!feasibility.can_tile(board, [O, O, O, O].iter().copied().collect()));
+        // S and Z alone leave a cell sticking out at each end.
+        assert!(!feasibility.can_tile(board, [S, S, S, S, S].iter().copied().collect()));
+    }
+
+    #[test]
+    fn unreachable_rows() {
+        use Shape::*;
+
+        // Every row has an empty cell under an overhang which neither an L
+        // nor a T can get into, although they tile the board if rows could
+        // be skipped.
+        let board = Board(0b0000111111_1111101111_0011100111_0110101111);
+        let available: ShapeCounts = [L, T, T].iter().copied().collect();
+        let mut feasibility = Feasibility::new();
+
+        assert!(!board.is_dead());
+        assert!(feasibility.can_tile(board, available));
+        assert!(!feasibility.can_clear_line(board, available));
+        assert!(!feasibility.is_feasible(board, available));
+    }
+
+    #[test]
+    fn never_rejects_a_played_queue() {
+        let mut random = Random::new();
+        let mut memo = HashMap::new();
+        let mut feasibility = Feasibility::new();
+        let mut checked = 0;
+
+        // Some starts are unsolvable, which is fine; they're skipped.
+        for _ in 0..500 {
+            let (start, moves) = match random_game(&mut random, &mut memo) {
+                Some(game) => game,
+                None => continue,
+            };
+
+            // Check every board on the way with the shapes which came after it.
+            let boards = std::iter::once(start).chain(moves.iter().map(|&(_, board)| board));
+            for (i, board) in boards.enumerate() {
+                let available: ShapeCounts = moves[i..].iter().map(|&(shape, _)| shape).collect();
+                assert!(feasibility.is_feasible(board, available), "{}", board);
+                checked += 1;
+            }
+        }
+
+        assert!(checked > 500);
+    }
+}
@@ -165,6 +165,16 @@ impl Board {
         (self.0 & mask) != 0
     }
 
+    /// Mirror the board horizontally, so column 0 becomes column 9.
+    pub fn mirror(self) -> Board {
+        let mut mirrored = 0;
+        for row in 0..4 {
+            let bits = (self.0 >> (row * 10)) & 0b1111111111;
+            mirrored |= (bits.reverse_bits() >> 54) << (row * 10);
+        }
+        Board(mirrored)
+    }
+
     /// Check whether the board has a cell that cannot be filled.
     ///
     /// If the two cells to the left and right of an empty cell are both full
@@ -310,6 +320,273 @@ impl Board {
             || check_col(self, COL_6, LEFT_6)
             || check_col(self, COL_7, LEFT_7)
     }
+
+    /// Check whether the empty cells are too unevenly split between the two
+    /// colors of a checkerboard.
+    ///
+    /// Color each cell by the parity of its row plus its column.  Every empty
+    /// cell must be filled by some future piece.  When lines are cleared, the
+    /// rows above move down, so a piece placed later may cover cells of this
+    /// board in rows which aren't adjacent.  But columns never move, and each
+    /// row of a piece always lands in a single row of this board.
+    ///
+    /// A horizontal run of cells in one row covers equally many cells of each
+    /// color if its length is even, and one more of either color if its length
+    /// is odd.  Every tetromino orientation has at most two odd runs, except
+    /// the vertical I, which has four.  But a vertical I needs four non-full
+    /// rows, and rows never become un-full, so it can only be placed while no
+    /// lines have been cleared, and then it covers four adjacent rows evenly.
+    ///
+    /// So each piece changes the difference between the colors by at most 2.
+    /// Filling *E* empty cells takes *E*/4 pieces, so if the difference is more
+    /// than 2&times;*E*/4, the board can't be filled.
+    pub fn has_unbalanced_checkerboard(self) -> bool {
+        const CHECKER: u64 = 0b1010101010_0101010101_1010101010_0101010101;
+
+        let empty = !self.0 & BOARD_MASK;
+        let pieces = empty.count_ones() / 4;
+        let difference = (empty & CHECKER).count_ones() as i32 - (empty & !CHECKER).count_ones() as i32;
+
+        difference.unsigned_abs() > 2 * pieces
+    }
+
+    /// Check whether the empty cells are too unevenly split between even and
+    /// odd columns.
+    ///
+    /// Columns never move when lines are cleared, so a piece always covers the
+    /// same number of cells in each column, no matter which rows it lands in.
+    /// Horizontal I, O, S, Z, and flat T pieces cover equally many cells in
+    /// even and odd columns.  Vertical T and every J and L cover three of one
+    /// and one of the other, a difference of 2.  A vertical I covers four cells
+    /// of one column, a difference of 4.
+    ///
+    /// A vertical I needs four non-full rows, so it can only be placed before
+    /// any line is cleared, into a column which is entirely empty now.  It
+    /// fills that column completely, so there can be at most one vertical I
+    /// per currently empty column.
+    ///
+    /// So *n* = *E*/4 pieces, of which at most *k* = min(*n*, empty columns)
+    /// are vertical I pieces, change the difference by at most 2*n* + 2*k*.  If
+    /// the difference is larger, the board can't be filled.
+    pub fn has_unbalanced_columns(self) -> bool {
+        const EVEN: u64 = 0b0101010101_0101010101_0101010101_0101010101;
+
+        let empty = !self.0 & BOARD_MASK;
+        let pieces = empty.count_ones() / 4;
+        let difference = (empty & EVEN).count_ones() as i32 - (empty & !EVEN).count_ones() as i32;
+
+        let not_empty = (self.0 >> 30) | (self.0 >> 20) | (self.0 >> 10) | self.0;
+        let empty_columns = (!not_empty & 0b1111111111).count_ones();
+
+        difference.unsigned_abs() > 2 * pieces + 2 * pieces.min(empty_columns)
+    }
+
+    /// Check whether the board has an empty cell that no tetromino can cover.
+    ///
+    /// Cells never become empty again, so every cell of a future piece is empty
+    /// now.  When lines are cleared, the rows of a later piece may land in rows
+    /// of this board which aren't adjacent, but they stay in order, they stay in
+    /// the same columns, and they are never in full rows.
+    ///
+    /// So for every tetromino orientation, this tries putting its rows into
+    /// every increasing choice of non-full rows, at every column.  If some
+    /// empty cell is not covered by any of these which fit entirely in empty
+    /// cells, no future piece can ever fill it.
+    ///
+    /// This generalizes [`has_isolated_cell`], which is faster.
+    ///
+    /// [`has_isolated_cell`]: Board::has_isolated_cell
+    pub fn has_unfillable_region(self) -> bool {
+        let empty = !self.0 & BOARD_MASK;
+        empty & !tetromino_coverage(empty, true) != 0
+    }
+
+    /// Check whether no line can ever be cleared, because every non-full row
+    /// has an empty cell that can't be filled before the first line clear.
+    ///
+    /// Until a line is cleared, rows don't move.  So every piece placed before
+    /// then lies in adjacent rows of this board, entirely in cells which are
+    /// empty now.  It also can't cover any cell that [`relaxed_coverage`]
+    /// misses.  That over-approximates SRS, so those cells can't be reached by
+    /// the next piece.  It is also monotone, so they can't be reached on any
+    /// board with more filled cells either, until rows move.
+    ///
+    /// The first line clear needs some row to be filled completely by those
+    /// pieces.  If every non-full row has an empty cell that none of them can
+    /// cover, no line can ever be cleared, and the board can't be filled.
+    ///
+    /// The SRS part is fairly slow, so it only runs when some empty cell has a
+    /// filled cell above it.  Otherwise a vertical I can be dropped straight
+    /// down onto every empty cell.
+    ///
+    /// [`relaxed_coverage`]: crate::vector::relaxed_coverage
+    pub fn has_unreachable_overhang(self) -> bool {
+        fn no_clearable_row(empty: u64, fillable: u64) -> bool {
+            let covered = tetromino_coverage(fillable, false);
+            (0..4).all(|row| {
+                let empty_row = (empty >> (row * 10)) & 0b1111111111;
+                let covered_row = (covered >> (row * 10)) & 0b1111111111;
+                empty_row & !covered_row != 0
+            })
+        }
+
+        let empty = !self.0 & BOARD_MASK;
+        if empty == 0 {
+            return false;
+        }
+        if no_clearable_row(empty, empty) {
+            return true;
+        }
+
+        let below_filled = (self.0 >> 10) | (self.0 >> 20) | (self.0 >> 30);
+        let mut unreachable = empty & below_filled;
+        for shape in Shape::ALL.iter().copied() {
+            if unreachable == 0 {
+                return false;
+            }
+            unreachable &= !crate::vector::relaxed_coverage(self, shape).0;
+        }
+        if unreachable == 0 {
+            return false;
+        }
+
+        no_clearable_row(empty, empty & !unreachable)
+    }
+
+    /// Check whether the board can't be filled, by any of the checks above,
+    /// cheapest first.
+    pub fn is_dead(self) -> bool {
+        self.has_isolated_cell()
+            || self.has_imbalanced_split()
+            || self.has_unbalanced_checkerboard()
+            || self.has_unbalanced_columns()
+            || self.has_unfillable_region()
+            || self.has_unreachable_overhang()
+    }
+
+    /// Every board one piece on which may still be filled, with the shape
+    /// placed, or none if this board is [dead](Board::is_dead).  Every graph
+    /// of legal boards is generated this way.
+    ///
+    /// Children are only checked with the cheapest culls, since each child is
+    /// checked in full when it is expanded in turn.  The same child can come
+    /// from more than one placement.
+    pub fn live_children(self) -> impl Iterator<Item = (Shape, Board)> {
+        let shapes: &[Shape] = if self.is_dead() { &[] } else { &Shape::ALL };
+        shapes.iter().flat_map(move |&shape| {
+            crate::vector::Placements::place(self, shape)
+                .canonical()
+                .map(move |(_, child)| (shape, child))
+                .filter(|&(_, child)| !child.has_isolated_cell() && !child.has_imbalanced_split())
+        })
+    }
+}
+
+/// Each tetromino orientation's cells, as one 10-bit mask per row from the
+/// bottom.  Orientations covering the most cells come first.
+pub(crate) const TETROMINOES: [(Shape, &[u64]); 19] = [
+    (Shape::T, &[0b111, 0b010]),
+    (Shape::T, &[0b010, 0b111]),
+    (Shape::J, &[0b111, 0b001]),
+    (Shape::L, &[0b111, 0b100]),
+    (Shape::J, &[0b100, 0b111]),
+    (Shape::L, &[0b001, 0b111]),
+    (Shape::S, &[0b011, 0b110]),
+    (Shape::Z, &[0b110, 0b011]),
+    (Shape::O, &[0b11, 0b11]),
+    (Shape::I, &[0b1111]),
+    (Shape::T, &[0b01, 0b11, 0b01]),
+    (Shape::T, &[0b10, 0b11, 0b10]),
+    (Shape::J, &[0b01, 0b01, 0b11]),
+    (Shape::J, &[0b11, 0b10, 0b10]),
+    (Shape::L, &[0b11, 0b01, 0b01]),
+    (Shape::L, &[0b10, 0b10, 0b11]),
+    (Shape::S, &[0b10, 0b11, 0b01]),
+    (Shape::Z, &[0b01, 0b11, 0b10]),
+    (Shape::I, &[0b1, 0b1, 0b1, 0b1]),
+];
+
+/// Find which of the `empty` cells can be covered by a tetromino lying entirely
+/// in `empty` cells.
+///
+/// With `skip_rows`, the rows of a tetromino may land in any increasing choice
+/// of rows, as they can after lines are cleared.  Otherwise they must be
+/// adjacent.
+fn tetromino_coverage(empty: u64, skip_rows: bool) -> u64 {
+    // Choices of rows by height, as bit sets.  Adjacent choices come first.
+    const ROW_SETS: [&[u32]; 5] = [
+        &[],
+        &[0b0001, 0b0010, 0b0100, 0b1000],
+        &[0b0011, 0b0110, 0b1100, 0b0101, 0b1010, 0b1001],
+        &[0b0111, 0b1110, 0b1011, 0b1101],
+        &[0b1111],
+    ];
+    const ADJACENT: [usize; 5] = [0, 4, 3, 2, 1];
+
+    // Columns where a run can start so that the whole run fits.
+    fn fits(empty: u64, mut run: u64) -> u64 {
+        let mut fits = 0b1111111111;
+        while run != 0 {
+            fits &= empty >> run.trailing_zeros();
+            run &= run - 1;
+        }
+        fits
+    }
+
+    // Cells covered by a run starting at any of `starts`.
+    fn spread(starts: u64, mut run: u64) -> u64 {
+        let mut cells = 0;
+        while run != 0 {
+            cells |= starts << run.trailing_zeros();
+            run &= run - 1;
+        }
+        cells & 0b1111111111
+    }
+
+    let rows = [
+        empty & 0b1111111111,
+        (empty >> 10) & 0b1111111111,
+        (empty >> 20) & 0b1111111111,
+        (empty >> 30) & 0b1111111111,
+    ];
+
+    // The loop usually finishes early, since the orientations covering the
+    // most cells come first.
+    let mut covered = [0; 4];
+    for (_shape, runs) in TETROMINOES.iter() {
+        let row_sets = ROW_SETS[runs.len()];
+        let row_sets = if skip_rows {
+            row_sets
+        } else {
+            &row_sets[..ADJACENT[runs.len()]]
+        };
+
+        for &row_set in row_sets {
+            let mut starts = 0b1111111111;
+            let mut row_bits = row_set;
+            for &run in runs.iter() {
+                let row = row_bits.trailing_zeros() as usize;
+                row_bits &= row_bits - 1;
+                starts &= fits(rows[row], run);
+            }
+            if starts == 0 {
+                continue;
+            }
+
+            let mut row_bits = row_set;
+            for &run in runs.iter() {
+                let row = row_bits.trailing_zeros() as usize;
+                row_bits &= row_bits - 1;
+                covered[row] |= spread(starts, run);
+            }
+        }
+
+        if (0..4).all(|row| rows[row] & !covered[row] == 0) {
+            break;
+        }
+    }
+
+    covered[0] | covered[1] << 10 | covered[2] << 20 | covered[3] << 30
 }
 
 impl Piece {
@@ -772,6 +1049,17 @@ impl Shape {
         ["I", "J", "L", "O", "S", "T", "Z"][self as usize]
     }
 
+    /// The shape which this shape becomes when the board is mirrored.
+    pub fn mirror(self) -> Shape {
+        match self {
+            Shape::J => Shape::L,
+            Shape::L => Shape::J,
+            Shape::S => Shape::Z,
+            Shape::Z => Shape::S,
+            shape => shape,
+        }
+    }
+
     /// Try to convert back from a `u8`.
     pub fn try_from(n: u8) -> Option<Shape> {
         match n {
@@ -853,3 +1141,72 @@ impl Orientation {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::gameplay::Board;
+    use crate::testing::{random_game, reaches_full, Random};
+
+    const FULL: Board = Board(0xFFFFF_FFFFF);
+
+    fn culls(board: Board) -> [(&'static str, bool); 4] {
+        [
+            ("checkerboard", board.has_unbalanced_checkerboard()),
+            ("columns", board.has_unbalanced_columns()),
+            ("unfillable", board.has_unfillable_region()),
+            ("overhang", board.has_unreachable_overhang()),
+        ]
+    }
+
+    #[test]
+    fn culls_keep_solvable_boards() {
+        let mut random = Random::new();
+        let mut memo = HashMap::new();
+        let mut checked = 0;
+
+        // Some starts are unsolvable, which is fine; they're skipped.
+        for _ in 0..1000 {
+            let (start, moves) = match random_game(&mut random, &mut memo) {
+                Some(game) => game,
+                None => continue,
+            };
+
+            let boards = std::iter::once(start).chain(moves.iter().map(|&(_, board)| board));
+            for board in boards.filter(|&board| board != FULL) {
+                for (name, culled) in culls(board).iter() {
+                    assert!(!culled, "{} culled a solvable board:{}", name, board);
+                }
+                checked += 1;
+            }
+        }
+
+        assert!(checked > 1000);
+    }
+
+    #[test]
+    fn culls_find_dead_boards() {
+        // Empty cells at (0, 0), (0, 2), (1, 1), and (1, 3) are all one color.
+        let checkerboard = Board(FULL.0 & !(1 | 1 << 2 | 1 << 11 | 1 << 13));
+        assert!(checkerboard.has_unbalanced_checkerboard());
+
+        // Four empty cells in even columns of the bottom row.
+        let columns = Board(FULL.0 & !(1 | 1 << 2 | 1 << 4 | 1 << 6));
+        assert!(columns.has_unbalanced_columns());
+
+        let unfillable = Board(0x407a2193ff);
+        assert!(unfillable.has_unfillable_region());
+        assert!(!unfillable.has_isolated_cell());
+
+        let overhang = Board(0xd7fcef787c);
+        assert!(overhang.has_unreachable_overhang());
+        assert!(!overhang.has_unfillable_region());
+
+        for board in [checkerboard, columns, unfillable, overhang].iter() {
+            assert!(board.is_dead());
+            assert!(!reaches_full(*board, &mut HashMap::new()));
+        }
+        assert!(!Board::empty().is_dead());
+    }
+}
@@ -1,7 +1,11 @@
 pub mod base64;
 pub mod board_list;
 pub mod brokenboard;
+pub mod feasibility;
 pub mod gameplay;
 pub mod piece_placer;
 pub mod queue;
 pub mod vector;
+
+#[cfg(test)]
+mod testing;
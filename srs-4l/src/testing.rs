@@ -0,0 +1,89 @@
+//! Random perfect clear games for the tests, checked only with the board's
+//! original culls, so they don't depend on the checks being tested.
+
+use std::collections::HashMap;
+
+use crate::gameplay::{Board, Shape};
+use crate::vector::Placements;
+
+/// A small xorshift generator, so the games are the same on every run
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new() -> Self {
+        Random(0x2545F4914F6CDD1D)
+    }
+
+    /// A number below `n`
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// Every board one piece on, with the shape placed
+pub(crate) fn children(board: Board) -> Vec<(Shape, Board)> {
+    let mut children: Vec<(Shape, Board)> = Shape::ALL
+        .iter()
+        .flat_map(|&shape| {
+            Placements::place(board, shape)
+                .canonical()
+                .map(move |(_, child)| (shape, child))
+        })
+        .collect();
+    children.sort_unstable();
+    children.dedup();
+    children
+}
+
+pub(crate) fn reaches_full(board: Board, memo: &mut HashMap<Board, bool>) -> bool {
+    if board == Board::full() {
+        return true;
+    }
+    if board.has_isolated_cell() || board.has_imbalanced_split() {
+        return false;
+    }
+    if let Some(&known) = memo.get(&board) {
+        return known;
+    }
+    let reaches = children(board)
+        .into_iter()
+        .any(|(_, child)| reaches_full(child, memo));
+    memo.insert(board, reaches);
+    reaches
+}
+
+/// A board a few random pieces in, and random placements from it to a perfect
+/// clear, or `None` if it can't reach one
+pub(crate) fn random_game(
+    random: &mut Random,
+    memo: &mut HashMap<Board, bool>,
+) -> Option<(Board, Vec<(Shape, Board)>)> {
+    // Searching from nearly empty boards is slow, so start a few pieces in.
+    let mut board = Board::empty();
+    for _ in 0..3 + random.below(4) {
+        let next = children(board);
+        if next.is_empty() {
+            break;
+        }
+        board = next[random.below(next.len())].1;
+    }
+    if !reaches_full(board, memo) {
+        return None;
+    }
+
+    let start = board;
+    let mut moves = Vec::new();
+    while board != Board::full() {
+        let next: Vec<(Shape, Board)> = children(board)
+            .into_iter()
+            .filter(|&(_, child)| reaches_full(child, memo))
+            .collect();
+        let (shape, child) = next[random.below(next.len())];
+        board = child;
+        moves.push((shape, child));
+    }
+    Some((start, moves))
+}
@@ -228,6 +228,66 @@ impl Placements {
     }
 }
 
+/// Find every cell which a piece of the given shape could cover, if every kick
+/// were allowed instead of only the first which fits.
+///
+/// This is an over-approximation of SRS: every position which is reachable
+/// according to [`Placements`] is also reachable here.  It is also monotone.
+/// Filling more cells of the board only removes viable positions, and every
+/// movement here only needs the destination to be viable, so a board with more
+/// filled cells never has more reachable positions.
+///
+/// Reachable positions are used, not just placeable ones, since every placed
+/// piece was reachable at the position where it locked.
+pub fn relaxed_coverage(board: Board, shape: Shape) -> Board {
+    let collision = &COLLISION[shape as usize];
+    let kicks = KICKS[shape as usize];
+
+    let viable = [
+        collision[0].viable(board),
+        collision[1].viable(board),
+        collision[2].viable(board),
+        collision[3].viable(board),
+    ];
+    let mut reachable = [
+        SPAWN & viable[0],
+        SPAWN & viable[1],
+        SPAWN & viable[2],
+        SPAWN & viable[3],
+    ];
+
+    let mut dirty = true;
+    while dirty {
+        dirty = false;
+
+        for this in 0..4 {
+            let cw = (this + 1) % 4;
+            let flip = (this + 2) % 4;
+            let ccw = (this + 3) % 4;
+
+            reachable[this] = reachable[this].flood_fill(viable[this]);
+
+            let more = [
+                (cw, kicks[this].kick_cw_any(reachable[this], viable[cw])),
+                (ccw, kicks[ccw].kick_ccw_any(reachable[this], viable[ccw])),
+                (flip, FLIP_KICKS[this].kick_flip_any(reachable[this], viable[flip])),
+            ];
+            for (to, more) in more {
+                if (reachable[to] & more) != more {
+                    reachable[to] |= more;
+                    dirty = true;
+                }
+            }
+        }
+    }
+
+    let mut covered = 0;
+    for orientation in 0..4 {
+        covered |= collision[orientation].cover(reachable[orientation]);
+    }
+    Board(covered & 0xFFFFF_FFFFF)
+}
+
 /// The core of the vectorized algorithm.  Not intended for public use.
 pub struct PlacementMachine {
     /// Shape of the pieces being placed.  **Constant** during iteration.
@@ -675,6 +735,14 @@ impl Collision {
         PVec(!collisions & self.mask)
     }
 
+    /// Find which cells are covered by a piece at any of the given positions.
+    pub fn cover(&self, positions: PVec) -> u64 {
+        positions.0 << self.shifts[0]
+            | positions.0 << self.shifts[1]
+            | positions.0 << self.shifts[2]
+            | positions.0 << self.shifts[3]
+    }
+
     /// Find which positions are placeable for this shape and orientation.  This
     /// will cut off positions from the top, *possibly even ones in bounds*,
     /// because if a piece were placed there, it might peek out the top of the
@@ -731,6 +799,16 @@ impl FlipKicks {
 
         PVec(to)
     }
+
+    /// Like [`kick_flip`](FlipKicks::kick_flip), but every kick is tried, not
+    /// only the first which fits.
+    pub fn kick_flip_any(&self, start: PVec, flip_viable: PVec) -> PVec {
+        let mut to = 0;
+        for num in 0..2 {
+            to |= start.0.rotate_left(self.rotates[num] as u32) & self.masks[num];
+        }
+        PVec(to & flip_viable.0)
+    }
 }
 
 
@@ -815,6 +893,26 @@ impl Kicks {
 
         PVec(to)
     }
+
+    /// Like [`kick_cw`](Kicks::kick_cw), but every kick is tried, not only the
+    /// first which fits.
+    pub fn kick_cw_any(&self, start: PVec, cw_viable: PVec) -> PVec {
+        let mut to = 0;
+        for num in 0..5 {
+            to |= start.0.rotate_left(self.rotates[num] as u32) & self.masks[num];
+        }
+        PVec(to & cw_viable.0)
+    }
+
+    /// Like [`kick_ccw`](Kicks::kick_ccw), but every kick is tried, not only
+    /// the first which fits.
+    pub fn kick_ccw_any(&self, start: PVec, ccw_viable: PVec) -> PVec {
+        let mut to = 0;
+        for num in 0..5 {
+            to |= (start.0 & self.masks[num]).rotate_right(self.rotates[num] as u32);
+        }
+        PVec(to & ccw_viable.0)
+    }
 }
 
 impl std::fmt::Debug for PVec {